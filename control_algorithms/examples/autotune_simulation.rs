@@ -0,0 +1,80 @@
+//! Relay auto-tuning of a simulated first-order plus dead time plant, on the host
+//!
+//! Run with
+//! `cargo run -p control_algorithms --example autotune_simulation --features std --target x86_64-unknown-linux-gnu`
+//!
+//! The measured ultimate gain and period are checked against the analytic values of the
+//! continuous plant `K e^(-L s) / (tau s + 1)`. The relay experiment relies on the
+//! describing function, which only looks at the fundamental of the oscillation, so the
+//! two agree to within a few percent for a plant with this much dead time.
+
+use std::f64::consts::PI;
+
+use control_algorithms::autotune::{tune, FirstOrderPlusDelay, RelayAutotuner, TuningRule};
+
+/// Static gain of the plant
+const GAIN: f32 = 2.0;
+
+/// Time constant of the plant in seconds
+const TIME_CONSTANT: f32 = 0.25;
+
+/// Dead time of the plant in samples
+const DELAY: usize = 500;
+
+/// Sample time in seconds
+const SAMPLE_TIME: f32 = 1e-3;
+
+/// Largest relative error allowed between the measured and analytic values
+const TOLERANCE: f64 = 0.05;
+
+/// Ultimate gain and period of the continuous plant, from the frequency where its phase
+/// reaches -180 degrees: `atan(w tau) + w L = pi`
+fn analytic() -> (f64, f64) {
+    let tau = TIME_CONSTANT as f64;
+    let delay = DELAY as f64 * SAMPLE_TIME as f64;
+
+    // Newton's method, the phase is monotonic in the frequency
+    let mut frequency = 1.0;
+    for _ in 0..100 {
+        let phase = (frequency * tau).atan() + frequency * delay - PI;
+        let slope = tau / (1.0 + (frequency * tau).powi(2)) + delay;
+        frequency -= phase / slope;
+    }
+
+    let ultimate_gain = (1.0 + (frequency * tau).powi(2)).sqrt() / GAIN as f64;
+    (ultimate_gain, 2.0 * PI / frequency)
+}
+
+fn main() {
+    let mut plant = FirstOrderPlusDelay::<DELAY, f32>::new(GAIN, TIME_CONSTANT);
+
+    // oscillate around 1 with a small hysteresis, so that the relay switches close to
+    // the phase crossover
+    let mut tuner = RelayAutotuner::new(1.0, 1.0 / GAIN, 0.3, 1e-3, 4, 120.0);
+
+    let measured = tune(&mut tuner, &mut plant, SAMPLE_TIME).expect("relay experiment failed");
+    let (ultimate_gain, ultimate_period) = analytic();
+
+    let gain_error = (measured.ultimate_gain as f64 - ultimate_gain).abs() / ultimate_gain;
+    let period_error = (measured.ultimate_period as f64 - ultimate_period).abs() / ultimate_period;
+
+    println!(
+        "ultimate gain:   measured {:.4}, analytic {:.4} ({:.1} %)",
+        measured.ultimate_gain,
+        ultimate_gain,
+        gain_error * 100.0
+    );
+    println!(
+        "ultimate period: measured {:.4} s, analytic {:.4} s ({:.1} %)",
+        measured.ultimate_period,
+        ultimate_period,
+        period_error * 100.0
+    );
+    println!(
+        "Tyreus-Luyben PI gains: {:?}",
+        measured.gains(TuningRule::TyreusLuybenPI)
+    );
+
+    assert!(gain_error < TOLERANCE, "ultimate gain is off");
+    assert!(period_error < TOLERANCE, "ultimate period is off");
+}
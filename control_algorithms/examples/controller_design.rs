@@ -0,0 +1,122 @@
+//! State-feedback design for the DC motor of `simulations/main.py`, on the host
+//!
+//! Run with
+//! `cargo run -p control_algorithms --example controller_design --features std --target x86_64-unknown-linux-gnu`
+//!
+//! The motor is discretized, an LQR gain and pole placement gains are designed, and the
+//! results are checked: the Riccati solution has to satisfy its equation, and the closed
+//! loop poles have to be stable or where they were placed.
+
+use control_algorithms::{
+    design::{const_array, dare, discretize, lqr, place, place_observer},
+    state_space::SMatrix,
+};
+use nalgebra::{Complex, Normed};
+
+/// Phase resistance in ohm
+const RESISTANCE: f64 = 1.8;
+
+/// Phase inductance in henry
+const INDUCTANCE: f64 = 0.85e-2;
+
+/// Viscous friction in N m s
+const FRICTION: f64 = 0.035;
+
+/// Rotor inertia in kg m^2
+const INERTIA: f64 = 0.032;
+
+/// Back-EMF constant in V s
+const BACK_EMF: f64 = 4.6;
+
+/// Torque constant in N m / A
+const TORQUE: f64 = 6.2;
+
+/// Sample time in seconds
+const SAMPLE_TIME: f64 = 1e-3;
+
+/// Largest relative residual of the Riccati equation
+const RESIDUAL_TOLERANCE: f64 = 1e-9;
+
+/// Largest distance between a placed pole and the closest closed-loop eigenvalue
+const POLE_TOLERANCE: f64 = 1e-6;
+
+/// Check that every requested pole is an eigenvalue of the closed-loop matrix
+fn check_poles(name: &str, closed_loop: SMatrix<f64, 3, 3>, poles: &[Complex<f64>; 3]) {
+    let eigenvalues = closed_loop.complex_eigenvalues();
+    println!("{} poles: {:?}", name, eigenvalues.as_slice());
+
+    for pole in poles {
+        let distance = eigenvalues
+            .iter()
+            .map(|eigenvalue| (eigenvalue - pole).norm())
+            .fold(f64::INFINITY, f64::min);
+
+        assert!(distance < POLE_TOLERANCE, "{}: pole {} is off", name, pole);
+    }
+}
+
+fn main() {
+    // states: current, position, speed
+    #[rustfmt::skip]
+    let a = SMatrix::<f64, 3, 3>::new(
+        -RESISTANCE / INDUCTANCE, 0.0, -BACK_EMF / INDUCTANCE,
+        0.0, 0.0, 1.0,
+        TORQUE / INERTIA, 0.0, -FRICTION / INERTIA,
+    );
+    let b = SMatrix::<f64, 3, 1>::new(1.0 / INDUCTANCE, 0.0, 0.0);
+    let c = SMatrix::<f64, 1, 3>::new(0.0, 1.0, 0.0);
+
+    let model = discretize(a, b, c, SAMPLE_TIME);
+
+    // same weights as the LQR design of the simulation
+    let q = SMatrix::<f64, 3, 3>::from_diagonal(&nalgebra::Vector3::new(0.0, 50.0, 0.0));
+    let r = SMatrix::<f64, 1, 1>::new(1.0 / 144.0);
+
+    let p = dare(&model.a, &model.b, &q, &r).expect("Riccati equation did not converge");
+    let (a_d, b_d) = (model.a, model.b);
+    let correction = a_d.transpose()
+        * p
+        * b_d
+        * (r + b_d.transpose() * p * b_d)
+            .try_inverse()
+            .expect("R + B' P B is singular")
+        * b_d.transpose()
+        * p
+        * a_d;
+    let residual = (a_d.transpose() * p * a_d - correction + q - p).norm() / p.norm();
+    println!("Riccati residual: {:e}", residual);
+    assert!(
+        residual < RESIDUAL_TOLERANCE,
+        "Riccati residual is too large"
+    );
+
+    let k = lqr(&model, &q, &r).expect("LQR design failed");
+    let radius = (model.a - model.b * k)
+        .complex_eigenvalues()
+        .iter()
+        .map(|eigenvalue| eigenvalue.norm())
+        .fold(0.0, f64::max);
+    println!("LQR spectral radius: {}", radius);
+    assert!(radius < 1.0, "LQR closed loop is unstable");
+    print!("{}", const_array("K_LQR", &k));
+
+    let poles = [
+        Complex::new(0.9, 0.1),
+        Complex::new(0.9, -0.1),
+        Complex::new(0.8, 0.0),
+    ];
+    let k = place(&model, &poles).expect("pole placement failed");
+    check_poles("state feedback", model.a - model.b * k, &poles);
+    print!("{}", const_array("K_PLACE", &k));
+
+    let poles = [
+        Complex::new(0.5, 0.0),
+        Complex::new(0.6, 0.0),
+        Complex::new(0.7, 0.0),
+    ];
+    let l = place_observer(&model, &poles).expect("observer design failed");
+    check_poles("observer", model.a - l * model.c, &poles);
+    print!("{}", const_array("L", &l));
+
+    println!("all designs are within tolerance");
+}
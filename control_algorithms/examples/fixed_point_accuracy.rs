@@ -0,0 +1,156 @@
+//! Accuracy of the fixed-point types against the float version, on the host
+//!
+//! Run with
+//! `cargo run -p control_algorithms --example fixed_point_accuracy --features std --target x86_64-unknown-linux-gnu`
+
+use control_algorithms::{
+    filters::{AverageFilter, MedianFilter},
+    foc::{
+        clarke_transform, inverse_clarke_transform, inverse_park_transform, park_transform,
+        Vector2, Vector3,
+    },
+    numeric::{Real, Q15, Q31},
+    pid::PIDController,
+};
+
+/// Largest error allowed for Q15 results
+const Q15_TOLERANCE: f32 = 1e-3;
+
+/// Largest error allowed for Q31 results
+const Q31_TOLERANCE: f32 = 1e-5;
+
+/// Absolute difference between a fixed-point result and the float reference
+fn error<T: Real>(value: T, reference: f32) -> f32 {
+    Real::abs(value.to_f32() - reference)
+}
+
+/// Run the field-oriented control transforms on a balanced set of phase currents
+fn foc<T: Real>(angle: f32) -> (Vector2<T>, Vector3<T>) {
+    let third = 2.0 * std::f32::consts::PI / 3.0;
+
+    let i_abc = Vector3::new(
+        T::from_f32(0.6 * angle.sin()),
+        T::from_f32(0.6 * (angle - third).sin()),
+        T::from_f32(0.6 * (angle + third).sin()),
+    );
+
+    let sin = T::from_f32(0.99 * angle.sin());
+    let cos = T::from_f32(0.99 * angle.cos());
+
+    let i_dq = park_transform(clarke_transform(i_abc), sin, cos);
+    let i_abc = inverse_clarke_transform(inverse_park_transform(i_dq, sin, cos));
+
+    (i_dq, i_abc)
+}
+
+/// Largest error of the transforms over one electrical revolution
+fn foc_error<T: Real>() -> f32 {
+    let mut max = 0.0;
+
+    for i in 0..1000 {
+        let angle = i as f32 * 2.0 * std::f32::consts::PI / 1000.0;
+
+        let (dq, abc) = foc::<T>(angle);
+        let (dq_ref, abc_ref) = foc::<f32>(angle);
+
+        for j in 0..2 {
+            max = Real::max(max, error(dq[j], dq_ref[j]));
+        }
+        for j in 0..3 {
+            max = Real::max(max, error(abc[j], abc_ref[j]));
+        }
+    }
+
+    max
+}
+
+/// Largest error of a PI controller following a sine reference
+fn pid_error<T: Real>() -> f32 {
+    let mut controller = PIDController::new(T::from_f32(0.5), T::from_f32(0.2), T::ZERO, None);
+    let mut reference = PIDController::new(0.5, 0.2, 0.0, None);
+
+    let mut max = 0.0;
+
+    for i in 0..500 {
+        let input = 0.5 * (i as f32 * 0.01).sin();
+        let measurement = 0.1;
+
+        let value = controller
+            .output(
+                T::from_f32(input),
+                T::from_f32(measurement),
+                T::from_f32(0.01),
+            )
+            .value;
+        let reference = reference.output(input, measurement, 0.01).value;
+
+        max = Real::max(max, error(value, reference));
+    }
+
+    max
+}
+
+/// Largest error of the averaging and median filters
+fn filter_error<T: Real>() -> f32 {
+    let mut average = AverageFilter::new([T::ZERO; 8]);
+    let mut median = MedianFilter::new([T::ZERO; 4]);
+    let mut average_reference = AverageFilter::new([0.0; 8]);
+    let mut median_reference = MedianFilter::new([0.0; 4]);
+
+    let mut max = 0.0;
+
+    for i in 0..200 {
+        let input = 0.9 * (i as f32 * 0.3).sin();
+
+        max = Real::max(
+            max,
+            error(
+                average.run(T::from_f32(input)),
+                average_reference.run(input),
+            ),
+        );
+        max = Real::max(
+            max,
+            error(median.run(T::from_f32(input)), median_reference.run(input)),
+        );
+    }
+
+    max
+}
+
+fn main() {
+    // saturation instead of wrapping
+    assert_eq!(Q15::from_f32(0.9) + Q15::from_f32(0.9), Q15::MAX);
+    assert_eq!(Q15::from_f32(-0.9) - Q15::from_f32(0.9), Q15::MIN);
+    assert_eq!(Q15::MIN * Q15::MIN, Q15::MAX);
+    assert_eq!(-Q31::MIN, Q31::MAX);
+    assert_eq!(Q31::from_f32(0.5) / Q31::ZERO, Q31::MAX);
+
+    // one is not representable, ONE is the largest value below it
+    assert_eq!(Q15::ONE, Q15::MAX);
+    assert_eq!(
+        Q15::ONE - Q15::from_f32(0.5),
+        Q15::from_f32(0.5) - Q15::EPSILON
+    );
+    assert_eq!(Q15::MAX * Q15::ONE, Q15::MAX - Q15::EPSILON);
+
+    let results = [
+        ("foc", foc_error::<Q15>(), foc_error::<Q31>()),
+        ("pid", pid_error::<Q15>(), pid_error::<Q31>()),
+        ("filters", filter_error::<Q15>(), filter_error::<Q31>()),
+    ];
+
+    for (name, q15, q31) in results {
+        println!("{}: Q15 error = {}, Q31 error = {}", name, q15, q31);
+
+        assert!(q15 < Q15_TOLERANCE, "{}: Q15 error too large", name);
+        assert!(q31 < Q31_TOLERANCE, "{}: Q31 error too large", name);
+    }
+
+    // f64 should agree with f32 to within f32 precision
+    let (dq, _) = foc::<f64>(1.0);
+    let (dq_ref, _) = foc::<f32>(1.0);
+    assert!(error(dq[0], dq_ref[0]) < 1e-5 && error(dq[1], dq_ref[1]) < 1e-5);
+
+    println!("all fixed-point results are within tolerance");
+}
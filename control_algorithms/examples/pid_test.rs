@@ -23,7 +23,7 @@ fn main() -> ! {
     let mut output_signal = [0f32; 1000];
 
     for (i, input) in input_signal.iter().enumerate() {
-        output_signal[i] = controller.output(*input, measurement, 0.001);
+        output_signal[i] = controller.output(*input, measurement, 0.001).value;
     }
 
     for i in output_signal {
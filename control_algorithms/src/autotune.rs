@@ -0,0 +1,304 @@
+//! Relay-feedback auto-tuning (Åström–Hägglund)
+//!
+//! A hysteretic relay drives the plant into a limit cycle, whose amplitude and period
+//! give the ultimate gain and period of the loop. These are turned into PID gains using
+//! one of the classic [`TuningRule`]s. The tuner can be driven by the real control loop
+//! or, using [`tune`], by a simulated [`Plant`] on the host (see
+//! `examples/autotune_simulation.rs`, which checks the result against the analytic values).
+
+use core::f32::consts::PI;
+
+use crate::filters::RingBuffer;
+use crate::numeric::Real;
+use crate::pid::PIDGains;
+
+/// Number of limit cycle periods that are skipped before measuring, to let the plant settle
+const SETTLING_CYCLES: u32 = 2;
+
+/// Errors that can occur during auto-tuning
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum AutotuneError {
+    /// The plant did not complete enough limit cycles in time
+    Timeout,
+    /// The oscillation amplitude was not larger than the relay hysteresis
+    NoOscillation,
+}
+
+/// Rule used to turn the ultimate gain and period into PID gains
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TuningRule {
+    /// Ziegler–Nichols PI
+    ZieglerNicholsPI,
+    /// Ziegler–Nichols PID
+    #[default]
+    ZieglerNichols,
+    /// Tyreus–Luyben PI, more robust and less oscillatory than Ziegler–Nichols
+    TyreusLuybenPI,
+    /// Tyreus–Luyben PID
+    TyreusLuyben,
+    /// Pessen integral rule
+    PessenIntegral,
+    /// Ziegler–Nichols variant with some overshoot
+    SomeOvershoot,
+    /// Ziegler–Nichols variant with no overshoot
+    NoOvershoot,
+}
+
+impl TuningRule {
+    /// Proportional gain and integral and derivative times, relative to the ultimate gain and period
+    fn coefficients(self) -> (f32, f32, f32) {
+        match self {
+            TuningRule::ZieglerNicholsPI => (0.45, 1.0 / 1.2, 0.0),
+            TuningRule::ZieglerNichols => (0.6, 0.5, 0.125),
+            TuningRule::TyreusLuybenPI => (1.0 / 3.2, 2.2, 0.0),
+            TuningRule::TyreusLuyben => (1.0 / 2.2, 2.2, 1.0 / 6.3),
+            TuningRule::PessenIntegral => (0.7, 0.4, 0.15),
+            TuningRule::SomeOvershoot => (0.33, 0.5, 1.0 / 3.0),
+            TuningRule::NoOvershoot => (0.2, 0.5, 1.0 / 3.0),
+        }
+    }
+}
+
+/// Ultimate gain and period measured by the relay experiment
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UltimateParameters<T = f32> {
+    /// Gain at which the loop oscillates
+    pub ultimate_gain: T,
+    /// Period of the oscillation in seconds
+    pub ultimate_period: T,
+}
+
+impl<T: Real> UltimateParameters<T> {
+    /// Calculate PID gains using the given rule
+    pub fn gains(&self, rule: TuningRule) -> PIDGains<T> {
+        let (k_p, t_i, t_d) = rule.coefficients();
+
+        let k_p = self.ultimate_gain * T::from_f32(k_p);
+        let t_i = self.ultimate_period * T::from_f32(t_i);
+        let t_d = self.ultimate_period * T::from_f32(t_d);
+
+        PIDGains {
+            k_p,
+            k_i: k_p / t_i,
+            k_d: k_p * t_d,
+        }
+    }
+}
+
+/// State of the auto-tuner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AutotuneState<T = f32> {
+    /// Still driving the relay experiment
+    Running,
+    /// Finished measuring
+    Done(UltimateParameters<T>),
+    /// Gave up
+    Failed(AutotuneError),
+}
+
+/// Relay-feedback auto-tuner
+pub struct RelayAutotuner<T: Real = f32> {
+    /// Measurement that the plant oscillates around
+    setpoint: T,
+    /// Output around which the relay switches
+    bias: T,
+    /// Relay amplitude
+    amplitude: T,
+    /// Relay hysteresis
+    hysteresis: T,
+    /// Number of limit cycle periods to average over
+    cycles: u32,
+    /// Time after which the experiment is aborted
+    timeout: T,
+    /// Whether the relay output is high
+    high: bool,
+    /// Time since the start of the experiment
+    time: T,
+    /// Time of the last switch from low to high
+    last_rising: Option<T>,
+    /// Completed limit cycle periods
+    completed: u32,
+    /// Largest measurement in the current period
+    peak: T,
+    /// Smallest measurement in the current period
+    trough: T,
+    /// Sum of the measured periods
+    period_sum: T,
+    /// Sum of the measured peak-to-peak amplitudes
+    amplitude_sum: T,
+    /// State of the experiment
+    state: AutotuneState<T>,
+}
+
+impl<T: Real> RelayAutotuner<T> {
+    /// Constructor with field values
+    ///
+    /// The relay output switches between `bias ± amplitude` whenever the error leaves
+    /// the `±hysteresis` band, and the result is averaged over `cycles` periods.
+    pub fn new(setpoint: T, bias: T, amplitude: T, hysteresis: T, cycles: u32, timeout: T) -> Self {
+        assert!(cycles > 0, "At least one cycle has to be measured!");
+
+        Self {
+            setpoint,
+            bias,
+            amplitude,
+            hysteresis,
+            cycles,
+            timeout,
+            high: true,
+            time: T::ZERO,
+            last_rising: None,
+            completed: 0,
+            peak: setpoint,
+            trough: setpoint,
+            period_sum: T::ZERO,
+            amplitude_sum: T::ZERO,
+            state: AutotuneState::Running,
+        }
+    }
+
+    /// State of the experiment
+    pub fn state(&self) -> AutotuneState<T> {
+        self.state
+    }
+
+    /// Feed a new measurement, and return the relay output to apply to the plant
+    ///
+    /// Once the experiment is finished, the output stays at the bias.
+    pub fn update(&mut self, measurement: T, delta_t: T) -> T {
+        if self.state != AutotuneState::Running {
+            return self.bias;
+        }
+
+        self.time += delta_t;
+        if self.time > self.timeout {
+            self.state = AutotuneState::Failed(AutotuneError::Timeout);
+            return self.bias;
+        }
+
+        self.peak = self.peak.max(measurement);
+        self.trough = self.trough.min(measurement);
+
+        let error = self.setpoint - measurement;
+
+        if !self.high && error > self.hysteresis {
+            self.high = true;
+            self.rising_edge();
+        } else if self.high && error < -self.hysteresis {
+            self.high = false;
+        }
+
+        match self.state {
+            AutotuneState::Running if self.high => self.bias + self.amplitude,
+            AutotuneState::Running => self.bias - self.amplitude,
+            _ => self.bias,
+        }
+    }
+
+    /// Finish a limit cycle period when the relay switches from low to high
+    fn rising_edge(&mut self) {
+        if let Some(last_rising) = self.last_rising {
+            self.completed += 1;
+
+            if self.completed > SETTLING_CYCLES {
+                self.period_sum += self.time - last_rising;
+                self.amplitude_sum += self.peak - self.trough;
+            }
+
+            if self.completed == SETTLING_CYCLES + self.cycles {
+                self.finish();
+            }
+        }
+
+        self.last_rising = Some(self.time);
+        self.peak = self.setpoint;
+        self.trough = self.setpoint;
+    }
+
+    /// Calculate the ultimate gain and period from the measured cycles
+    fn finish(&mut self) {
+        let cycles = T::from_f32(self.cycles as f32);
+        let period = self.period_sum / cycles;
+        let amplitude = self.amplitude_sum / (cycles + cycles);
+
+        if amplitude <= self.hysteresis {
+            self.state = AutotuneState::Failed(AutotuneError::NoOscillation);
+            return;
+        }
+
+        // describing function of a relay with hysteresis
+        let amplitude = (amplitude * amplitude - self.hysteresis * self.hysteresis).sqrt();
+        let ultimate_gain = T::from_f32(4.0 / PI) * self.amplitude / amplitude;
+
+        self.state = AutotuneState::Done(UltimateParameters {
+            ultimate_gain,
+            ultimate_period: period,
+        });
+    }
+}
+
+/// Plant that can be simulated one sample at a time
+pub trait Plant<T: Real = f32> {
+    /// Apply an input for `delta_t` seconds and return the new measurement
+    fn step(&mut self, input: T, delta_t: T) -> T;
+}
+
+/// First-order plant with a dead time of `DELAY` samples
+pub struct FirstOrderPlusDelay<const DELAY: usize, T: Real = f32> {
+    /// Static gain
+    gain: T,
+    /// Time constant in seconds
+    time_constant: T,
+    /// Output of the first-order part
+    state: T,
+    /// Inputs waiting to be applied
+    delay: RingBuffer<DELAY, T>,
+}
+
+impl<const DELAY: usize, T: Real> FirstOrderPlusDelay<DELAY, T> {
+    /// Constructor with field values, starting at rest
+    pub fn new(gain: T, time_constant: T) -> Self {
+        Self {
+            gain,
+            time_constant,
+            state: T::ZERO,
+            delay: RingBuffer::new([T::ZERO; DELAY]),
+        }
+    }
+}
+
+impl<const DELAY: usize, T: Real> Plant<T> for FirstOrderPlusDelay<DELAY, T> {
+    fn step(&mut self, input: T, delta_t: T) -> T {
+        // oldest input leaves the delay line
+        let delayed = if DELAY == 0 {
+            input
+        } else {
+            let oldest = self.delay[0];
+            self.delay.insert(input);
+            oldest
+        };
+
+        self.state += (self.gain * delayed - self.state) * delta_t / self.time_constant;
+        self.state
+    }
+}
+
+/// Run the relay experiment against a plant until it finishes
+pub fn tune<T: Real, P: Plant<T>>(
+    tuner: &mut RelayAutotuner<T>,
+    plant: &mut P,
+    delta_t: T,
+) -> Result<UltimateParameters<T>, AutotuneError> {
+    let mut measurement = plant.step(tuner.bias, delta_t);
+
+    loop {
+        match tuner.state() {
+            AutotuneState::Running => {
+                let input = tuner.update(measurement, delta_t);
+                measurement = plant.step(input, delta_t);
+            }
+            AutotuneState::Done(parameters) => return Ok(parameters),
+            AutotuneState::Failed(error) => return Err(error),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Cascaded position, velocity and current control
+
+use crate::numeric::Real;
+use crate::pid::{PIDOutput, TwoDOFController};
+
+/// Outermost loop that is active in a [`CascadeController`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CascadeMode {
+    /// Only the current loop runs, the current reference is given directly
+    #[default]
+    Torque,
+    /// The velocity loop generates the current reference
+    Velocity,
+    /// The position loop generates the velocity reference
+    Position,
+}
+
+/// References given to the cascade, only the ones used by the selected mode are read
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CascadeReference<T = f32> {
+    /// Position reference
+    pub position: T,
+    /// Velocity reference, also used for the feedforward of the outer loops
+    pub velocity: T,
+    /// Acceleration reference, used for the feedforward of the outer loops
+    pub acceleration: T,
+    /// Current reference
+    pub current: T,
+}
+
+/// Measured signals fed back into the cascade
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CascadeFeedback<T = f32> {
+    /// Measured position
+    pub position: T,
+    /// Measured velocity
+    pub velocity: T,
+    /// Measured (q-axis) current
+    pub current: T,
+}
+
+/// Single loop of a cascade, updated once every `decimation` calls
+pub struct CascadeStage<T: Real = f32> {
+    /// Controller of this loop
+    pub controller: TwoDOFController<T>,
+    /// Number of calls between updates
+    decimation: u32,
+    /// Calls since the last update
+    counter: u32,
+    /// Time since the last update
+    elapsed: T,
+    /// Output held between updates
+    output: PIDOutput<T>,
+    /// Whether the stage has run since it was last enabled
+    active: bool,
+}
+
+impl<T: Real> CascadeStage<T> {
+    /// Constructor with field values
+    ///
+    /// Output limits of the controller become the limits of the reference of the next loop.
+    pub fn new(controller: TwoDOFController<T>, decimation: u32) -> Self {
+        assert!(decimation > 0, "Decimation must be at least 1!");
+
+        Self {
+            controller,
+            decimation,
+            counter: 0,
+            elapsed: T::ZERO,
+            output: PIDOutput::default(),
+            active: false,
+        }
+    }
+
+    /// Run the controller if enough calls have passed, otherwise hold the last output
+    ///
+    /// The first update after the stage is enabled starts from `previous_output`, the
+    /// command that this stage takes over from, and holds it until the controller runs.
+    /// A controller without integral gain cannot be preloaded and jumps to its
+    /// proportional output on that first run.
+    fn update(
+        &mut self,
+        input: T,
+        measurement: T,
+        velocity: T,
+        acceleration: T,
+        previous_output: T,
+        delta_t: T,
+    ) -> PIDOutput<T> {
+        if !self.active {
+            self.controller
+                .initialize(previous_output, input, measurement, velocity, acceleration);
+            self.active = true;
+            self.counter = 0;
+            self.elapsed = T::ZERO;
+            self.output = PIDOutput {
+                value: previous_output,
+                saturated: false,
+            };
+        }
+
+        self.elapsed += delta_t;
+        self.counter += 1;
+
+        if self.counter >= self.decimation {
+            self.output =
+                self.controller
+                    .output(input, measurement, velocity, acceleration, self.elapsed);
+            self.counter = 0;
+            self.elapsed = T::ZERO;
+        }
+
+        self.output
+    }
+
+    /// Mark the stage as bypassed, so that it is preloaded once it runs again
+    fn bypass(&mut self) {
+        self.active = false;
+    }
+}
+
+/// Position, velocity and current loops in series
+///
+/// Outer loops are typically run at a fraction of the current loop rate by giving
+/// their stages a decimation larger than one. Feedforward of the position stage is
+/// driven by the velocity and acceleration references (use a velocity gain of one to
+/// pass the reference velocity through), the velocity stage uses the same references,
+/// and the current stage uses the measured velocity so that its velocity gain can
+/// compensate the back-EMF.
+pub struct CascadeController<T: Real = f32> {
+    /// Position loop, outputs the velocity reference
+    pub position: CascadeStage<T>,
+    /// Velocity loop, outputs the current reference
+    pub velocity: CascadeStage<T>,
+    /// Current loop, outputs the voltage
+    pub current: CascadeStage<T>,
+    /// Outermost active loop
+    mode: CascadeMode,
+    /// Last velocity reference given to the velocity loop
+    velocity_reference: T,
+    /// Last current reference given to the current loop
+    current_reference: T,
+    /// Last output of the current loop
+    voltage: T,
+}
+
+impl<T: Real> CascadeController<T> {
+    /// Constructor with field values, starting in torque mode
+    pub fn new(
+        position: CascadeStage<T>,
+        velocity: CascadeStage<T>,
+        current: CascadeStage<T>,
+    ) -> Self {
+        Self {
+            position,
+            velocity,
+            current,
+            mode: CascadeMode::Torque,
+            velocity_reference: T::ZERO,
+            current_reference: T::ZERO,
+            voltage: T::ZERO,
+        }
+    }
+
+    /// Select the outermost active loop
+    pub fn with_mode(mut self, mode: CascadeMode) -> Self {
+        self.set_mode(mode);
+        self
+    }
+
+    /// Select the outermost active loop
+    ///
+    /// Loops that become active continue from the reference they take over, as long as
+    /// they have integral gain.
+    pub fn set_mode(&mut self, mode: CascadeMode) {
+        if mode != self.mode {
+            self.position.bypass();
+            self.velocity.bypass();
+        }
+
+        self.mode = mode;
+    }
+
+    /// Outermost active loop
+    pub fn mode(&self) -> CascadeMode {
+        self.mode
+    }
+
+    /// Velocity reference used in the last update
+    pub fn velocity_reference(&self) -> T {
+        self.velocity_reference
+    }
+
+    /// Current reference used in the last update
+    pub fn current_reference(&self) -> T {
+        self.current_reference
+    }
+
+    /// Update all active loops and return the output of the current loop
+    ///
+    /// `delta_t` is the time since the last call, the decimated stages accumulate it.
+    pub fn output(
+        &mut self,
+        reference: CascadeReference<T>,
+        feedback: CascadeFeedback<T>,
+        delta_t: T,
+    ) -> PIDOutput<T> {
+        self.velocity_reference = match self.mode {
+            CascadeMode::Position => {
+                self.position
+                    .update(
+                        reference.position,
+                        feedback.position,
+                        reference.velocity,
+                        reference.acceleration,
+                        self.velocity_reference,
+                        delta_t,
+                    )
+                    .value
+            }
+            _ => reference.velocity,
+        };
+
+        self.current_reference = match self.mode {
+            CascadeMode::Position | CascadeMode::Velocity => {
+                self.velocity
+                    .update(
+                        self.velocity_reference,
+                        feedback.velocity,
+                        reference.velocity,
+                        reference.acceleration,
+                        self.current_reference,
+                        delta_t,
+                    )
+                    .value
+            }
+            CascadeMode::Torque => reference.current,
+        };
+
+        let output = self.current.update(
+            self.current_reference,
+            feedback.current,
+            feedback.velocity,
+            T::ZERO,
+            self.voltage,
+            delta_t,
+        );
+        self.voltage = output.value;
+
+        output
+    }
+}
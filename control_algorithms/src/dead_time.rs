@@ -0,0 +1,80 @@
+//! Dead-time compensation
+//!
+//! During the dead time both switches of a leg are off and the phase current flows
+//! through one of the diodes, so the phase voltage is set by the direction of the current
+//! instead of the PWM. Over a period this loses `t_d f_pwm V_dc` of voltage against the
+//! current, which distorts the currents when the voltages are small, i.e. at low speed.
+//! The error is fed forward by adding the lost duty cycle in the direction of each phase
+//! current.
+//!
+//! The direction of the current is uncertain close to zero (ripple, noise), so the
+//! correction ramps linearly through a band around zero instead of switching with the
+//! sign.
+
+use crate::foc::{clarke_transform, Vector2, Vector3};
+use crate::numeric::Real;
+
+/// Feedforward compensation of the inverter dead time
+pub struct DeadTimeCompensation<T: Real = f32> {
+    /// Duty cycle lost to the dead time, `t_d f_pwm`
+    duty: T,
+    /// Bus voltage
+    bus_voltage: T,
+    /// Current at which the full correction is applied
+    current_band: T,
+}
+
+impl<T: Real> DeadTimeCompensation<T> {
+    /// Constructor with the dead time in seconds, the PWM frequency in hertz, the bus
+    /// voltage and the current above which the full correction is applied
+    pub fn new(dead_time: T, pwm_frequency: T, bus_voltage: T, current_band: T) -> Self {
+        let duty = dead_time * pwm_frequency;
+        assert!(
+            duty >= T::ZERO && duty < T::from_f32(0.5),
+            "Dead time has to be shorter than half of the PWM period!"
+        );
+        assert!(current_band > T::ZERO, "Current band has to be positive!");
+
+        Self {
+            duty,
+            bus_voltage,
+            current_band,
+        }
+    }
+
+    /// Update the bus voltage, e.g. from a measurement
+    pub fn set_bus_voltage(&mut self, bus_voltage: T) {
+        self.bus_voltage = bus_voltage;
+    }
+
+    /// Average voltage lost on a phase carrying more than the current band
+    pub fn voltage_error(&self) -> T {
+        self.duty * self.bus_voltage
+    }
+
+    /// Direction of a phase current, ramping from -1 to 1 through the current band
+    fn direction(&self, current: T) -> T {
+        (current / self.current_band).clamp(-T::ONE, T::ONE)
+    }
+
+    /// Duty cycle corrections of phases a, b and c for the phase currents, positive
+    /// flowing out of the inverter
+    pub fn corrections(&self, currents: Vector3<T>) -> [T; 3] {
+        [currents[0], currents[1], currents[2]].map(|current| self.duty * self.direction(current))
+    }
+
+    /// Add the corrections to the duty cycles of a modulator, keeping them between 0 and 1
+    pub fn compensate(&self, duties: [T; 3], currents: Vector3<T>) -> [T; 3] {
+        let corrections = self.corrections(currents);
+        [0, 1, 2].map(|i| (duties[i] + corrections[i]).clamp(T::ZERO, T::ONE))
+    }
+
+    /// The corrections as a stationary frame voltage, to be added to the reference before
+    /// the modulator instead
+    ///
+    /// The common part of the phase errors does not reach the motor and is dropped.
+    pub fn voltage(&self, currents: Vector3<T>) -> Vector2<T> {
+        let error = self.voltage_error();
+        clarke_transform(currents.map(|current| error * self.direction(current)))
+    }
+}
@@ -0,0 +1,209 @@
+//! Offline design of discrete state-space controllers and observers
+//!
+//! Only available with the `std` feature, for use on the host. Replaces the
+//! `control.lqr` design in `simulations/main.py`: models are discretized, gains are
+//! designed for the discrete model, and the results are printed with [`const_array`]
+//! so they can be pasted into the firmware and used with the
+//! [`state_space`](crate::state_space) types. `examples/controller_design.rs` runs the
+//! design for the motor of the simulation and checks the results.
+
+use std::format;
+use std::string::String;
+use std::vec;
+use std::vec::Vec;
+
+use nalgebra::{Complex, DMatrix, SMatrix};
+
+use crate::state_space::StateSpace;
+
+/// Largest number of doubling steps when solving the Riccati equation
+const MAX_ITERATIONS: usize = 100;
+
+/// Relative change at which the Riccati solution is considered converged
+const TOLERANCE: f64 = 1e-12;
+
+/// Largest imaginary part allowed in the characteristic polynomial
+const IMAGINARY_TOLERANCE: f64 = 1e-9;
+
+/// Errors that can occur during the design
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum DesignError {
+    /// A matrix that has to be inverted is singular (e.g. `R` is not positive definite)
+    Singular,
+    /// The Riccati equation did not converge (e.g. the model is not stabilizable)
+    NoConvergence,
+    /// The model is not controllable from its input
+    Uncontrollable,
+    /// The model is not observable from its output
+    Unobservable,
+    /// Complex poles do not come in conjugate pairs
+    UnpairedPoles,
+}
+
+/// Discretize a continuous model `x' = A x + B u`, `y = C x` with a zero-order hold
+pub fn discretize<const N: usize, const U: usize, const Y: usize>(
+    a: SMatrix<f64, N, N>,
+    b: SMatrix<f64, N, U>,
+    c: SMatrix<f64, Y, N>,
+    delta_t: f64,
+) -> StateSpace<f64, N, U, Y> {
+    // exp([A B; 0 0] T) = [A_d B_d; 0 I]
+    let mut augmented = DMatrix::<f64>::zeros(N + U, N + U);
+    augmented.view_mut((0, 0), (N, N)).copy_from(&(a * delta_t));
+    augmented.view_mut((0, N), (N, U)).copy_from(&(b * delta_t));
+
+    let exponential = augmented.exp();
+
+    StateSpace::new(
+        exponential.fixed_view::<N, N>(0, 0).into_owned(),
+        exponential.fixed_view::<N, U>(0, N).into_owned(),
+        c,
+    )
+}
+
+/// Solve the discrete algebraic Riccati equation
+/// `P = A' P A - A' P B (R + B' P B)^-1 B' P A + Q`
+///
+/// Uses the structured doubling algorithm, which converges quadratically.
+pub fn dare<const N: usize, const U: usize>(
+    a: &SMatrix<f64, N, N>,
+    b: &SMatrix<f64, N, U>,
+    q: &SMatrix<f64, N, N>,
+    r: &SMatrix<f64, U, U>,
+) -> Result<SMatrix<f64, N, N>, DesignError> {
+    let identity = SMatrix::<f64, N, N>::identity();
+    let r_inv = r.try_inverse().ok_or(DesignError::Singular)?;
+
+    let mut a_k = *a;
+    let mut g_k = b * r_inv * b.transpose();
+    let mut h_k = *q;
+
+    for _ in 0..MAX_ITERATIONS {
+        let w = (identity + g_k * h_k)
+            .try_inverse()
+            .ok_or(DesignError::Singular)?;
+
+        let h_next = h_k + a_k.transpose() * h_k * w * a_k;
+        g_k += a_k * w * g_k * a_k.transpose();
+        a_k = a_k * w * a_k;
+
+        let change = (h_next - h_k).norm();
+        h_k = h_next;
+
+        if change <= TOLERANCE * h_k.norm().max(1.0) {
+            return Ok(h_k);
+        }
+    }
+
+    Err(DesignError::NoConvergence)
+}
+
+/// Discrete LQR gain `K` minimizing `sum(x' Q x + u' R u)` under `u = -K x`
+pub fn lqr<const N: usize, const U: usize, const Y: usize>(
+    model: &StateSpace<f64, N, U, Y>,
+    q: &SMatrix<f64, N, N>,
+    r: &SMatrix<f64, U, U>,
+) -> Result<SMatrix<f64, U, N>, DesignError> {
+    let (a, b) = (&model.a, &model.b);
+    let p = dare(a, b, q, r)?;
+
+    let gain = (r + b.transpose() * p * b)
+        .try_inverse()
+        .ok_or(DesignError::Singular)?;
+
+    Ok(gain * b.transpose() * p * a)
+}
+
+/// Evaluate the characteristic polynomial with the given roots at `A`
+fn characteristic_polynomial<const N: usize>(
+    a: &SMatrix<f64, N, N>,
+    poles: &[Complex<f64>; N],
+) -> Result<SMatrix<f64, N, N>, DesignError> {
+    // coefficients, lowest order first
+    let mut coefficients: Vec<Complex<f64>> = vec![Complex::new(1.0, 0.0)];
+    for pole in poles {
+        let mut next = vec![Complex::new(0.0, 0.0); coefficients.len() + 1];
+        for (i, coefficient) in coefficients.iter().enumerate() {
+            next[i + 1] += coefficient;
+            next[i] -= coefficient * pole;
+        }
+        coefficients = next;
+    }
+
+    let mut result = SMatrix::<f64, N, N>::zeros();
+    let mut power = SMatrix::<f64, N, N>::identity();
+    for coefficient in coefficients {
+        if coefficient.im.abs() > IMAGINARY_TOLERANCE * coefficient.re.abs().max(1.0) {
+            return Err(DesignError::UnpairedPoles);
+        }
+
+        result += power * coefficient.re;
+        power = a * power;
+    }
+
+    Ok(result)
+}
+
+/// State-feedback gain `K` placing the poles of `A - B K` (Ackermann's formula)
+///
+/// Complex poles have to be given in conjugate pairs.
+pub fn place<const N: usize, const Y: usize>(
+    model: &StateSpace<f64, N, 1, Y>,
+    poles: &[Complex<f64>; N],
+) -> Result<SMatrix<f64, 1, N>, DesignError> {
+    let mut controllability = SMatrix::<f64, N, N>::zeros();
+    let mut column = model.b;
+    for i in 0..N {
+        controllability.set_column(i, &column);
+        column = model.a * column;
+    }
+
+    let controllability = controllability
+        .try_inverse()
+        .ok_or(DesignError::Uncontrollable)?;
+
+    Ok(controllability.row(N - 1) * characteristic_polynomial(&model.a, poles)?)
+}
+
+/// Observer gain `L` placing the poles of `A - L C` (Ackermann's formula)
+///
+/// Complex poles have to be given in conjugate pairs.
+pub fn place_observer<const N: usize, const U: usize>(
+    model: &StateSpace<f64, N, U, 1>,
+    poles: &[Complex<f64>; N],
+) -> Result<SMatrix<f64, N, 1>, DesignError> {
+    let mut observability = SMatrix::<f64, N, N>::zeros();
+    let mut row = model.c;
+    for i in 0..N {
+        observability.set_row(i, &row);
+        row *= model.a;
+    }
+
+    let observability = observability
+        .try_inverse()
+        .ok_or(DesignError::Unobservable)?;
+
+    Ok(characteristic_polynomial(&model.a, poles)? * observability.column(N - 1))
+}
+
+/// Print a matrix as a row-major `f32` const array, to be loaded with
+/// `SMatrix::from_row_slice` on the firmware
+pub fn const_array<const R: usize, const C: usize>(
+    name: &str,
+    matrix: &SMatrix<f64, R, C>,
+) -> String {
+    let values: Vec<String> = matrix
+        .transpose()
+        .iter()
+        .map(|&value| format!("{:?}", value as f32))
+        .collect();
+
+    format!(
+        "/// {}x{} matrix, row-major\npub const {}: [f32; {}] = [{}];\n",
+        R,
+        C,
+        name,
+        R * C,
+        values.join(", ")
+    )
+}
@@ -0,0 +1,99 @@
+//! Field weakening by voltage feedback
+//!
+//! Once the back-EMF uses up the available voltage, the current loops saturate and the
+//! speed cannot increase any further. A negative d-axis current weakens the magnet flux,
+//! trading current for voltage. Here the d-axis current reference is generated by an
+//! integrating loop on the voltage utilization, so it only kicks in when it is needed
+//! and adapts to the bus voltage and motor parameters on its own.
+
+use crate::foc::Vector2;
+use crate::numeric::Real;
+use crate::pid::{AntiWindup, PIDController};
+
+/// Current references produced by [`FieldWeakening`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FieldWeakeningOutput<T = f32> {
+    /// d-axis current reference (zero or negative)
+    pub d_current: T,
+    /// Largest q-axis current magnitude left within the current limit
+    pub q_limit: T,
+}
+
+impl<T: Real> FieldWeakeningOutput<T> {
+    /// Clamp a q-axis current reference to what is left within the current limit
+    pub fn limit_q(&self, q_current: T) -> T {
+        q_current.clamp(-self.q_limit, self.q_limit)
+    }
+}
+
+/// Closed-loop field-weakening controller
+pub struct FieldWeakening<T: Real = f32> {
+    /// Loop from voltage utilization to d-axis current
+    controller: PIDController<T>,
+    /// Magnitude of the current limit circle
+    max_current: T,
+    /// Voltage utilization above which the field is weakened
+    utilization: T,
+}
+
+impl<T: Real> FieldWeakening<T> {
+    /// Constructor with the gains of the loop, the current limit and the largest
+    /// (magnitude of) d-axis current that may be injected
+    ///
+    /// The gains are in amps per unit of voltage utilization, a pure integral loop
+    /// (`k_p = 0`) is usually enough.
+    pub fn new(k_p: T, k_i: T, max_current: T, max_d_current: T) -> Self {
+        assert!(
+            max_d_current <= max_current,
+            "d-axis current cannot be larger than the current limit!"
+        );
+
+        Self {
+            controller: PIDController::new(k_p, k_i, T::ZERO, None)
+                .with_output_limits(-max_d_current, T::ZERO)
+                .with_anti_windup(AntiWindup::Clamping),
+            max_current,
+            utilization: T::from_f32(0.95),
+        }
+    }
+
+    /// Voltage utilization that the loop regulates to while weakening, 0.95 by default
+    ///
+    /// Leaves some headroom for the current controllers to react to transients.
+    pub fn with_utilization(mut self, utilization: T) -> Self {
+        self.utilization = utilization;
+        self
+    }
+
+    /// Stop weakening the field
+    pub fn reset(&mut self) {
+        self.controller.reset();
+    }
+
+    /// Update the d-axis current reference from the requested dq voltage (before it was
+    /// limited) and the largest voltage available, e.g. [`VoltageLimiter::max_voltage`]
+    ///
+    /// When overmodulating, pass [`VoltageLimiter::linear_voltage`] instead so that the
+    /// field is weakened before the modulator leaves its linear range.
+    ///
+    /// [`VoltageLimiter::max_voltage`]: crate::foc::VoltageLimiter::max_voltage
+    /// [`VoltageLimiter::linear_voltage`]: crate::foc::VoltageLimiter::linear_voltage
+    pub fn update(
+        &mut self,
+        voltage: Vector2<T>,
+        max_voltage: T,
+        delta_t: T,
+    ) -> FieldWeakeningOutput<T> {
+        let usage = voltage.dot(&voltage).sqrt() / max_voltage;
+        let d_current = self
+            .controller
+            .output(self.utilization, usage, delta_t)
+            .value;
+
+        let q_limit = (self.max_current * self.max_current - d_current * d_current)
+            .max(T::ZERO)
+            .sqrt();
+
+        FieldWeakeningOutput { d_current, q_limit }
+    }
+}
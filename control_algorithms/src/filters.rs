@@ -2,6 +2,8 @@
 
 use core::ops::{Index, IndexMut};
 
+use crate::numeric::Real;
+
 /// Ring buffer
 pub struct RingBuffer<const SIZE: usize, T> {
     /// Data
@@ -45,48 +47,51 @@ impl<const SIZE: usize, T> IndexMut<usize> for RingBuffer<SIZE, T> {
 }
 
 /// Simple averaging filter with a sliding window backed by a ring buffer
-pub struct AverageFilter<const SIZE: usize> {
+pub struct AverageFilter<const SIZE: usize, T: Real = f32> {
     /// Data
-    data: RingBuffer<SIZE, f32>,
+    data: RingBuffer<SIZE, T>,
 }
 
-impl<const SIZE: usize> AverageFilter<SIZE> {
+impl<const SIZE: usize, T: Real> AverageFilter<SIZE, T> {
     /// Initialize the filter, starting with 0
-    pub fn new(initial_values: [f32; SIZE]) -> Self {
+    pub fn new(initial_values: [T; SIZE]) -> Self {
         Self {
             data: RingBuffer::new(initial_values),
         }
     }
 
     /// Get the next filtered output
-    pub fn run(&mut self, new_value: f32) -> f32 {
+    pub fn run(&mut self, new_value: T) -> T {
         self.data.insert(new_value);
 
-        let mut total = 0.0;
+        // scale every sample so that the sum can't overflow fixed-point types
+        let scale = T::from_f32(1.0 / SIZE as f32);
+
+        let mut total = T::ZERO;
         for i in 0..SIZE {
-            total += self.data[i];
+            total += self.data[i] * scale;
         }
 
-        total / SIZE as f32
+        total
     }
 }
 
 /// Median filter with a sliding window backed by a ring buffer and sorted using insertion sort
-pub struct MedianFilter<const SIZE: usize> {
+pub struct MedianFilter<const SIZE: usize, T: Real = f32> {
     /// Data
-    data: RingBuffer<SIZE, f32>,
+    data: RingBuffer<SIZE, T>,
 }
 
-impl<const SIZE: usize> MedianFilter<SIZE> {
+impl<const SIZE: usize, T: Real> MedianFilter<SIZE, T> {
     /// Initialize the filter
-    pub fn new(initial_values: [f32; SIZE]) -> Self {
+    pub fn new(initial_values: [T; SIZE]) -> Self {
         Self {
             data: RingBuffer::new(initial_values),
         }
     }
 
     /// Get the next filtered output
-    pub fn run(&mut self, new_value: f32) -> f32 {
+    pub fn run(&mut self, new_value: T) -> T {
         self.data.insert(new_value);
 
         let mut data = self.data.copy();
@@ -94,7 +99,8 @@ impl<const SIZE: usize> MedianFilter<SIZE> {
 
         if SIZE % 2 == 0 {
             // e.g. 4: 2 & 3 -> 1 & 2
-            (data[(SIZE / 2) - 1] + data[(SIZE) / 2]) / 2.0
+            let half = T::from_f32(0.5);
+            data[(SIZE / 2) - 1] * half + data[(SIZE) / 2] * half
         } else {
             // e.g. 5: 3 -> 2
             data[SIZE / 2]
@@ -1,49 +1,384 @@
 //! Field-oriented control functions
-use micromath::F32Ext;
 pub use nalgebra::{Matrix2, Matrix2x3, Matrix3x2, Vector2, Vector3};
 
+use crate::numeric::Real;
+
+/// sqrt(3) / 2
+const SQRT_3_2: f32 = 0.866_025_4;
+
+/// 1 / sqrt(3)
+const FRAC_1_SQRT_3: f32 = 0.577_350_26;
+
+/// 2 sqrt(3) / pi, six-step fundamental over the radius of the inscribed circle
+const SIX_STEP_RATIO: f32 = 1.102_658;
+
 /// Apply clarke transform to input signal
-pub fn clarke_transform(input_signal: Vector3<f32>) -> Vector2<f32> {
-    2f32 / 3f32
-        * Matrix2x3::<f32>::new(
-            // Defining Park matrix
-            1f32,
-            -0.5f32,
-            -0.5f32,
-            0f32,
-            3f32.sqrt() / 2f32,
-            -(3f32.sqrt()) / 2f32,
-        )
-        * input_signal
+pub fn clarke_transform<T: Real>(input_signal: Vector3<T>) -> Vector2<T> {
+    // The 2/3 scaling is part of the matrix so that the intermediate
+    // results stay in range for fixed-point types
+    Matrix2x3::<T>::new(
+        // Defining Park matrix
+        T::from_f32(2. / 3.),
+        T::from_f32(-1. / 3.),
+        T::from_f32(-1. / 3.),
+        T::ZERO,
+        T::from_f32(FRAC_1_SQRT_3),
+        T::from_f32(-FRAC_1_SQRT_3),
+    ) * input_signal
 }
 
 /// Apply inverse clarke transform to input signal
-pub fn inverse_clarke_transform(input_signal: Vector2<f32>) -> Vector3<f32> {
-    Matrix3x2::<f32>::new(
-        1f32,
-        0f32,
-        -0.5f32,
-        3f32.sqrt() / 2f32,
-        -0.5f32,
-        -(3f32.sqrt()) / 2f32,
+pub fn inverse_clarke_transform<T: Real>(input_signal: Vector2<T>) -> Vector3<T> {
+    Matrix3x2::<T>::new(
+        T::ONE,
+        T::ZERO,
+        T::from_f32(-0.5),
+        T::from_f32(SQRT_3_2),
+        T::from_f32(-0.5),
+        T::from_f32(-SQRT_3_2),
     ) * input_signal
 }
 
 /// Apply park transform to input signal
-pub fn park_transform(input_signal: Vector2<f32>, angle_sin: f32, angle_cos: f32) -> Vector2<f32> {
-    Matrix2::<f32>::new(
+pub fn park_transform<T: Real>(input_signal: Vector2<T>, angle_sin: T, angle_cos: T) -> Vector2<T> {
+    Matrix2::<T>::new(
         // Defining clarke matrix
         angle_cos, angle_sin, -angle_sin, angle_cos,
     ) * input_signal
 }
 
 /// Apply inverse park transform to input signal
-pub fn inverse_park_transform(
-    input_signal: Vector2<f32>,
-    angle_sin: f32,
-    angle_cos: f32,
-) -> Vector2<f32> {
-    Matrix2::<f32>::new(angle_cos, -angle_sin, angle_sin, angle_cos) * input_signal
+pub fn inverse_park_transform<T: Real>(
+    input_signal: Vector2<T>,
+    angle_sin: T,
+    angle_cos: T,
+) -> Vector2<T> {
+    Matrix2::<T>::new(angle_cos, -angle_sin, angle_sin, angle_cos) * input_signal
+}
+
+/// Rotor frame parameters of a permanent magnet synchronous motor
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MotorParameters<T = f32> {
+    /// d-axis inductance in henry
+    pub d_inductance: T,
+    /// q-axis inductance in henry
+    pub q_inductance: T,
+    /// Permanent magnet flux linkage in weber
+    pub flux_linkage: T,
+}
+
+impl<T: Real> MotorParameters<T> {
+    /// Constructor with field values
+    pub fn new(d_inductance: T, q_inductance: T, flux_linkage: T) -> Self {
+        Self {
+            d_inductance,
+            q_inductance,
+            flux_linkage,
+        }
+    }
+}
+
+/// Calculate the dq voltages that cancel the cross-coupling and back-EMF terms
+///
+/// The result is added to the outputs of the d and q current controllers, so that they
+/// only have to handle the resistance and inductance. `electrical_speed` is in rad/s,
+/// and `current_dq` can be either the measured currents or their references.
+pub fn decoupling_feedforward<T: Real>(
+    current_dq: Vector2<T>,
+    electrical_speed: T,
+    motor: &MotorParameters<T>,
+) -> Vector2<T> {
+    Vector2::new(
+        -electrical_speed * motor.q_inductance * current_dq[1],
+        electrical_speed * (motor.d_inductance * current_dq[0] + motor.flux_linkage),
+    )
+}
+
+/// Calculate the electromagnetic torque produced by the given dq currents
+pub fn torque<T: Real>(current_dq: Vector2<T>, pole_pairs: T, motor: &MotorParameters<T>) -> T {
+    let reluctance = (motor.d_inductance - motor.q_inductance) * current_dq[0];
+
+    T::from_f32(1.5) * pole_pairs * (motor.flux_linkage + reluctance) * current_dq[1]
+}
+
+/// Calculate the dq currents with the given magnitude that produce the most torque
+///
+/// Uses the closed form of the maximum torque per ampere trajectory. For a surface
+/// motor (`L_d = L_q`) this is all q-axis current, for an interior motor part of the
+/// current goes to the d axis to use the reluctance torque. The q-axis current is
+/// positive, negate it for negative torque.
+pub fn mtpa_current<T: Real>(current: T, motor: &MotorParameters<T>) -> Vector2<T> {
+    let saliency = motor.q_inductance - motor.d_inductance;
+    let flux = motor.flux_linkage;
+
+    // i_d = (flux - sqrt(flux^2 + 8 saliency^2 i^2)) / (4 saliency), rewritten so that
+    // it stays finite without saliency
+    let root = (flux * flux + T::from_f32(8.0) * saliency * saliency * current * current).sqrt();
+    let d_current = -T::from_f32(2.0) * saliency * current * current / (flux + root);
+
+    let q_current = (current * current - d_current * d_current)
+        .max(T::ZERO)
+        .sqrt();
+
+    Vector2::new(d_current, q_current)
+}
+
+/// Table mapping a torque command to dq currents on the MTPA trajectory
+///
+/// Generated once from the motor parameters, so that no square roots have to be
+/// solved in the control loop. Torques are linearly interpolated between `N` points
+/// evenly spaced in current magnitude, and limited to the torque at the largest current.
+pub struct MtpaTable<const N: usize, T: Real = f32> {
+    /// Torque at each point, strictly increasing
+    torques: [T; N],
+    /// dq currents at each point
+    currents: [Vector2<T>; N],
+}
+
+impl<const N: usize, T: Real> MtpaTable<N, T> {
+    /// Generate the table up to the given current magnitude
+    pub fn new(motor: &MotorParameters<T>, pole_pairs: T, max_current: T) -> Self {
+        assert!(N > 1, "MTPA table needs at least two points!");
+
+        let mut torques = [T::ZERO; N];
+        let mut currents = [Vector2::zeros(); N];
+
+        for i in 0..N {
+            let magnitude = max_current * T::from_f32(i as f32 / (N - 1) as f32);
+            currents[i] = mtpa_current(magnitude, motor);
+            torques[i] = torque(currents[i], pole_pairs, motor);
+        }
+
+        Self { torques, currents }
+    }
+
+    /// Largest torque in the table
+    pub fn max_torque(&self) -> T {
+        self.torques[N - 1]
+    }
+
+    /// Look up the dq current references for a torque command
+    pub fn currents(&self, torque: T) -> Vector2<T> {
+        let magnitude = torque.abs();
+        let sign = if torque < T::ZERO { -T::ONE } else { T::ONE };
+
+        let mut current = self.currents[N - 1];
+        for i in 1..N {
+            if magnitude <= self.torques[i] {
+                let (x_0, x_1) = (self.torques[i - 1], self.torques[i]);
+                let fraction = (magnitude - x_0) / (x_1 - x_0);
+
+                current =
+                    self.currents[i - 1] + (self.currents[i] - self.currents[i - 1]) * fraction;
+                break;
+            }
+        }
+
+        Vector2::new(current[0], sign * current[1])
+    }
+}
+
+/// Shape of the region of voltage vectors that the inverter can produce
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VoltageLimit {
+    /// Circle inscribed in the hexagon, radius `V_dc / sqrt(3)`, the linear range of SVPWM
+    #[default]
+    Circle,
+    /// Full hexagon, up to `2 V_dc / 3` along the phase axes
+    Hexagon,
+    /// Circle of the six-step fundamental, radius `2 V_dc / pi`, for use with
+    /// [`svpwm_overmodulation`](crate::svpwm::svpwm_overmodulation)
+    SixStep,
+}
+
+/// How a voltage vector outside the limit is brought back inside
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VoltagePriority {
+    /// Scale both axes by the same factor, keeping the direction of the vector
+    #[default]
+    Proportional,
+    /// Keep `v_d` and only reduce `v_q`, keeping the flux (and field weakening) intact
+    DAxis,
+}
+
+/// Output of the [`VoltageLimiter`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LimitedVoltage<T: Real = f32> {
+    /// Limited dq voltage
+    pub voltage: Vector2<T>,
+    /// Requested minus limited voltage on each axis, e.g. for back-calculation anti-windup
+    pub saturation: Vector2<T>,
+    /// Whether the requested voltage was outside the limit
+    pub saturated: bool,
+}
+
+/// Limits the dq voltage vector to what the inverter can produce from the bus voltage
+pub struct VoltageLimiter<T: Real = f32> {
+    /// Shape of the limit
+    shape: VoltageLimit,
+    /// How the vector is reduced
+    priority: VoltagePriority,
+    /// Distance from the origin to the edges of the hexagon, `V_dc / sqrt(3)`
+    radius: T,
+}
+
+impl<T: Real> VoltageLimiter<T> {
+    /// Create a limiter for the given bus voltage, using the circle and proportional scaling
+    pub fn new(bus_voltage: T) -> Self {
+        Self {
+            shape: VoltageLimit::Circle,
+            priority: VoltagePriority::Proportional,
+            radius: bus_voltage * T::from_f32(FRAC_1_SQRT_3),
+        }
+    }
+
+    /// Set the shape of the limit
+    pub fn with_shape(mut self, shape: VoltageLimit) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    /// Set how the vector is reduced
+    pub fn with_priority(mut self, priority: VoltagePriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Update the bus voltage, e.g. from a measurement
+    pub fn set_bus_voltage(&mut self, bus_voltage: T) {
+        self.radius = bus_voltage * T::from_f32(FRAC_1_SQRT_3);
+    }
+
+    /// Largest voltage magnitude that is available in every direction
+    pub fn max_voltage(&self) -> T {
+        match self.shape {
+            VoltageLimit::Circle | VoltageLimit::Hexagon => self.radius,
+            VoltageLimit::SixStep => self.circle_radius(),
+        }
+    }
+
+    /// Largest voltage magnitude within the linear range of the modulator, `V_dc / sqrt(3)`
+    ///
+    /// Beyond it the output only follows on average over a revolution, with low-order
+    /// harmonics, e.g. the target of field weakening when overmodulating.
+    pub fn linear_voltage(&self) -> T {
+        self.radius
+    }
+
+    /// Radius of the circular limits
+    fn circle_radius(&self) -> T {
+        match self.shape {
+            VoltageLimit::SixStep => self.radius * T::from_f32(SIX_STEP_RATIO),
+            _ => self.radius,
+        }
+    }
+
+    /// Projections of a stationary frame vector onto the normals of the hexagon edges
+    fn projections(vector: Vector2<T>) -> [T; 3] {
+        let half = T::from_f32(0.5);
+        let sqrt_3_2 = T::from_f32(SQRT_3_2);
+
+        [
+            sqrt_3_2 * vector[0] + half * vector[1],
+            vector[1],
+            half * vector[1] - sqrt_3_2 * vector[0],
+        ]
+    }
+
+    /// Largest magnitude allowed along a unit vector in the stationary frame
+    fn max_along(&self, direction: Vector2<T>) -> T {
+        match self.shape {
+            VoltageLimit::Circle | VoltageLimit::SixStep => self.circle_radius(),
+            VoltageLimit::Hexagon => {
+                let projection = Self::projections(direction)
+                    .into_iter()
+                    .fold(T::ZERO, |max, value| max.max(value.abs()));
+
+                self.radius / projection
+            }
+        }
+    }
+
+    /// Limit a dq voltage vector, given the sine and cosine of the angle used to transform it
+    ///
+    /// The angle is only used for the hexagon, as its edges are fixed in the stationary frame.
+    pub fn limit(&self, voltage: Vector2<T>, angle_sin: T, angle_cos: T) -> LimitedVoltage<T> {
+        let limited = match self.priority {
+            VoltagePriority::Proportional => self.scale(voltage, angle_sin, angle_cos),
+            VoltagePriority::DAxis => self.limit_q(voltage, angle_sin, angle_cos),
+        };
+
+        let saturation = voltage - limited;
+
+        LimitedVoltage {
+            voltage: limited,
+            saturation,
+            saturated: saturation != Vector2::zeros(),
+        }
+    }
+
+    /// Scale the whole vector back onto the limit
+    fn scale(&self, voltage: Vector2<T>, angle_sin: T, angle_cos: T) -> Vector2<T> {
+        let usage = match self.shape {
+            VoltageLimit::Circle | VoltageLimit::SixStep => {
+                voltage.dot(&voltage).sqrt() / self.circle_radius()
+            }
+            VoltageLimit::Hexagon => {
+                let stationary = inverse_park_transform(voltage, angle_sin, angle_cos);
+                Self::projections(stationary)
+                    .into_iter()
+                    .fold(T::ZERO, |max, value| max.max(value.abs()))
+                    / self.radius
+            }
+        };
+
+        if usage > T::ONE {
+            voltage / usage
+        } else {
+            voltage
+        }
+    }
+
+    /// Limit `v_d` on its own, then give `v_q` what is left
+    fn limit_q(&self, voltage: Vector2<T>, angle_sin: T, angle_cos: T) -> Vector2<T> {
+        let d_axis = Vector2::new(angle_cos, angle_sin);
+        let q_axis = Vector2::new(-angle_sin, angle_cos);
+
+        let d_limit = self.max_along(d_axis);
+        let v_d = voltage[0].clamp(-d_limit, d_limit);
+
+        let q_limit = match self.shape {
+            VoltageLimit::Circle | VoltageLimit::SixStep => {
+                let radius = self.circle_radius();
+                (radius * radius - v_d * v_d).max(T::ZERO).sqrt()
+            }
+            VoltageLimit::Hexagon => {
+                // each edge allows |v_d a + v_q b| <= radius, solve for |v_q|
+                let sign = if voltage[1] < T::ZERO {
+                    -T::ONE
+                } else {
+                    T::ONE
+                };
+                let d_projections = Self::projections(d_axis);
+                let q_projections = Self::projections(q_axis);
+
+                let mut q_limit = voltage[1].abs();
+                for (a, b) in d_projections.into_iter().zip(q_projections) {
+                    if b != T::ZERO {
+                        let offset = if b < T::ZERO {
+                            -sign * v_d * a
+                        } else {
+                            sign * v_d * a
+                        };
+                        q_limit = q_limit.min(((self.radius - offset) / b.abs()).max(T::ZERO));
+                    }
+                }
+                q_limit
+            }
+        };
+
+        Vector2::new(v_d, voltage[1].clamp(-q_limit, q_limit))
+    }
 }
 
 // /// Apply clarke and park transform to input signal
@@ -0,0 +1,213 @@
+//! High-frequency injection for sensorless operation at zero and low speed
+//!
+//! A square wave voltage alternating every sample is added to `v_d` of the estimated
+//! rotor frame, ahead of [`inverse_park_transform`](crate::foc::inverse_park_transform).
+//! With a salient motor (`L_d < L_q`), part of the resulting current ripple shows up on
+//! the estimated q axis whenever the estimate is off, proportional to `sin(2 * error)`.
+//! This error drives a [`PhaseLockedLoop`].
+//!
+//! Saliency cannot tell north from south, so the estimate may lock on to the rotor
+//! angle plus π. [`HfiEstimator::detect_polarity`] resolves this using magnetic
+//! saturation: a d-axis current along the magnet saturates the iron and lowers `L_d`,
+//! which increases the d-axis ripple.
+
+use crate::foc::{park_transform, Vector2};
+use crate::numeric::Float;
+use crate::pll::{AngleEstimate, PhaseLockedLoop};
+
+/// Progress of the polarity detection
+#[derive(Clone, Copy, Debug, Default, PartialEq, defmt::Format)]
+pub enum Polarity {
+    /// Not detected yet, the estimate may be off by π
+    #[default]
+    Unknown,
+    /// Detection is running, [`HfiEstimator::d_current`] has to be added to the d-axis
+    /// current reference
+    Detecting,
+    /// The estimate was confirmed to point at the north pole
+    Confirmed,
+    /// The estimate was off by π and has been corrected
+    Corrected,
+}
+
+/// State of the polarity detection
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PolarityDetection<T> {
+    /// Amplitude of the d-axis current pulses
+    current: T,
+    /// Samples spent on each pulse
+    samples: u32,
+    /// Samples spent so far
+    count: u32,
+    /// Sum of the d-axis response during the positive pulse
+    positive: T,
+    /// Sum of the d-axis response during the negative pulse
+    negative: T,
+}
+
+/// Square wave pulsating injection estimator
+pub struct HfiEstimator<T: Float = f32> {
+    /// Injection amplitude in volt
+    voltage: T,
+    /// Scales the q-axis response to `sin(2 * error)`, `2 L_d L_q / (L_q - L_d)`
+    gain: T,
+    /// Sign of the injection applied before the last update
+    sign: T,
+    /// Last measured `alpha/beta` currents
+    previous_current: Vector2<T>,
+    /// Change of the current over the previous sample
+    previous_difference: Vector2<T>,
+    /// Demodulated response to a positive injection, in the estimated rotor frame
+    response: Vector2<T>,
+    /// Loop tracking the rotor angle
+    pll: PhaseLockedLoop<T>,
+    /// Result of the polarity detection
+    polarity: Polarity,
+    /// Running polarity detection
+    detection: PolarityDetection<T>,
+}
+
+impl<T: Float> HfiEstimator<T> {
+    /// Constructor with injection amplitude, motor inductances and the bandwidth of the
+    /// tracking loop
+    ///
+    /// Requires a salient motor with `d_inductance < q_inductance`.
+    pub fn new(voltage: T, d_inductance: T, q_inductance: T, bandwidth: T) -> Self {
+        assert!(
+            d_inductance < q_inductance,
+            "HFI needs the d-axis inductance to be smaller than the q-axis inductance!"
+        );
+
+        let two = T::from_f32(2.0);
+
+        Self {
+            voltage,
+            gain: two * d_inductance * q_inductance / (q_inductance - d_inductance),
+            sign: T::ONE,
+            previous_current: Vector2::zeros(),
+            previous_difference: Vector2::zeros(),
+            response: Vector2::zeros(),
+            pll: PhaseLockedLoop::new(bandwidth),
+            polarity: Polarity::Unknown,
+            detection: PolarityDetection {
+                current: T::ZERO,
+                samples: 0,
+                count: 0,
+                positive: T::ZERO,
+                negative: T::ZERO,
+            },
+        }
+    }
+
+    /// Current estimate
+    pub fn estimate(&self) -> AngleEstimate<T> {
+        self.pll.estimate()
+    }
+
+    /// Injection voltage to add to `v_d` for the next sample
+    pub fn injection(&self) -> T {
+        self.sign * self.voltage
+    }
+
+    /// Add the injection to the d axis of a `dq` voltage vector
+    pub fn inject(&self, voltage: Vector2<T>) -> Vector2<T> {
+        Vector2::new(voltage[0] + self.injection(), voltage[1])
+    }
+
+    /// Measured `alpha/beta` currents without the injection ripple, for the current loops
+    pub fn fundamental_current(&self) -> Vector2<T> {
+        let half = T::from_f32(0.5);
+        self.previous_current - self.previous_difference * half
+    }
+
+    /// Demodulated current response to a positive injection sample, in the estimated
+    /// rotor frame
+    pub fn response(&self) -> Vector2<T> {
+        self.response
+    }
+
+    /// Result of the polarity detection
+    pub fn polarity(&self) -> Polarity {
+        self.polarity
+    }
+
+    /// Start the polarity detection
+    ///
+    /// The estimate has to be locked and the rotor at rest. A d-axis current pulse of
+    /// `current` and one of `-current` are applied for `samples` samples each.
+    pub fn detect_polarity(&mut self, current: T, samples: u32) {
+        self.polarity = Polarity::Detecting;
+        self.detection = PolarityDetection {
+            current,
+            samples,
+            count: 0,
+            positive: T::ZERO,
+            negative: T::ZERO,
+        };
+    }
+
+    /// Offset to add to the d-axis current reference while detecting the polarity
+    pub fn d_current(&self) -> T {
+        match self.polarity {
+            Polarity::Detecting if self.detection.count < self.detection.samples => {
+                self.detection.current
+            }
+            Polarity::Detecting => -self.detection.current,
+            _ => T::ZERO,
+        }
+    }
+
+    /// Feed the `alpha/beta` currents measured after the last injection and return the
+    /// updated estimate
+    pub fn update(&mut self, current: Vector2<T>, delta_t: T) -> AngleEstimate<T> {
+        let half = T::from_f32(0.5);
+
+        // the fundamental changes slowly, so it cancels in the difference of two
+        // consecutive changes, while the injected ripple changes sign every sample
+        let difference = current - self.previous_current;
+        let ripple = (difference - self.previous_difference) * (half * self.sign);
+        self.previous_current = current;
+        self.previous_difference = difference;
+
+        let angle = self.pll.estimate().angle;
+        self.response = park_transform(ripple, angle.sin(), angle.cos());
+
+        // q-axis response is voltage * delta_t * sin(2 * error) / gain
+        let error = half * self.response[1] * self.gain / (self.voltage * delta_t);
+        let estimate = self.pll.track(error.clamp(-T::ONE, T::ONE), delta_t);
+
+        if self.polarity == Polarity::Detecting {
+            self.update_polarity();
+        }
+
+        self.sign = -self.sign;
+        estimate
+    }
+
+    /// Accumulate the d-axis response of the current pulses, and flip the estimate when
+    /// the negative pulse saturated more
+    fn update_polarity(&mut self) {
+        let detection = &mut self.detection;
+
+        // skip the first half of each pulse while the current settles
+        let settle = detection.samples / 2;
+        let count = detection.count;
+        if count >= settle && count < detection.samples {
+            detection.positive += self.response[0];
+        } else if count >= detection.samples + settle {
+            detection.negative += self.response[0];
+        }
+
+        detection.count += 1;
+
+        if detection.count >= detection.samples * 2 {
+            if detection.positive >= detection.negative {
+                self.polarity = Polarity::Confirmed;
+            } else {
+                let angle = self.pll.estimate().angle;
+                self.pll.initialize(-angle.sin(), -angle.cos());
+                self.polarity = Polarity::Corrected;
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Linear Kalman filter with fixed-size matrices
+
+pub use nalgebra::{SMatrix, SVector};
+
+use crate::linalg::invert;
+use crate::numeric::Real;
+
+/// Errors that can occur while updating the filter
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum KalmanError {
+    /// The innovation covariance could not be inverted
+    SingularCovariance,
+}
+
+/// Discrete linear Kalman filter with `N` states, `M` measurements and `U` inputs
+///
+/// The model is `x[k+1] = A x[k] + B u[k] + w`, `z[k] = C x[k] + v`, where the
+/// process and measurement noise `w` and `v` have the covariances `Q` and `R`.
+pub struct KalmanFilter<T: Real, const N: usize, const M: usize, const U: usize> {
+    /// State transition matrix
+    pub a: SMatrix<T, N, N>,
+    /// Input matrix
+    pub b: SMatrix<T, N, U>,
+    /// Measurement matrix
+    pub c: SMatrix<T, M, N>,
+    /// Process noise covariance
+    pub q: SMatrix<T, N, N>,
+    /// Measurement noise covariance
+    pub r: SMatrix<T, M, M>,
+    /// State estimate
+    x: SVector<T, N>,
+    /// Estimate covariance
+    p: SMatrix<T, N, N>,
+}
+
+impl<T: Real, const N: usize, const M: usize, const U: usize> KalmanFilter<T, N, M, U> {
+    /// Constructor with field values, starting from a zero state with identity covariance
+    pub fn new(
+        a: SMatrix<T, N, N>,
+        b: SMatrix<T, N, U>,
+        c: SMatrix<T, M, N>,
+        q: SMatrix<T, N, N>,
+        r: SMatrix<T, M, M>,
+    ) -> Self {
+        Self {
+            a,
+            b,
+            c,
+            q,
+            r,
+            x: SVector::zeros(),
+            p: SMatrix::identity(),
+        }
+    }
+
+    /// Start from the given state estimate and covariance
+    pub fn with_initial_state(mut self, x: SVector<T, N>, p: SMatrix<T, N, N>) -> Self {
+        self.x = x;
+        self.p = p;
+        self
+    }
+
+    /// State estimate
+    pub fn state(&self) -> &SVector<T, N> {
+        &self.x
+    }
+
+    /// Estimate covariance
+    pub fn covariance(&self) -> &SMatrix<T, N, N> {
+        &self.p
+    }
+
+    /// Propagate the estimate through the model with input `u`
+    pub fn predict(&mut self, u: &SVector<T, U>) {
+        self.x = self.a * self.x + self.b * u;
+        self.p = self.a * self.p * self.a.transpose() + self.q;
+    }
+
+    /// Correct the estimate with measurement `z`
+    pub fn correct(&mut self, z: &SVector<T, M>) -> Result<(), KalmanError> {
+        let innovation = z - self.c * self.x;
+        let s = self.c * self.p * self.c.transpose() + self.r;
+        let s_inv = invert(s).ok_or(KalmanError::SingularCovariance)?;
+        let k = self.p * self.c.transpose() * s_inv;
+
+        self.x += k * innovation;
+
+        // Joseph form, keeps the covariance symmetric and positive definite
+        let i_kc = SMatrix::<T, N, N>::identity() - k * self.c;
+        self.p = i_kc * self.p * i_kc.transpose() + k * self.r * k.transpose();
+
+        Ok(())
+    }
+
+    /// Predict with input `u`, then correct with measurement `z`
+    pub fn update(&mut self, u: &SVector<T, U>, z: &SVector<T, M>) -> Result<(), KalmanError> {
+        self.predict(u);
+        self.correct(z)
+    }
+}
+
+/// Mechanical model estimating angle, speed and load torque
+///
+/// States are `[angle, speed, load torque]`, the input is the motor torque, and the
+/// measurement is the (unwrapped) angle.
+pub type MechanicalKalmanFilter<T = f32> = KalmanFilter<T, 3, 1, 1>;
+
+impl<T: Real> MechanicalKalmanFilter<T> {
+    /// Create the filter for a rotor with the given inertia [kg m^2], sampled every `delta_t`
+    ///
+    /// `speed_noise` and `torque_noise` are the variances added to the speed and load
+    /// torque every sample, and `angle_noise` is the variance of the angle measurement.
+    pub fn mechanical(
+        inertia: T,
+        delta_t: T,
+        speed_noise: T,
+        torque_noise: T,
+        angle_noise: T,
+    ) -> Self {
+        let half = T::from_f32(0.5);
+        let dt_j = delta_t / inertia;
+
+        // exact discretization of angle' = speed, speed' = (torque - load) / J, load' = 0
+        let a = SMatrix::<T, 3, 3>::new(
+            T::ONE,
+            delta_t,
+            -half * delta_t * dt_j,
+            T::ZERO,
+            T::ONE,
+            -dt_j,
+            T::ZERO,
+            T::ZERO,
+            T::ONE,
+        );
+        let b = SMatrix::<T, 3, 1>::new(half * delta_t * dt_j, dt_j, T::ZERO);
+        let c = SMatrix::<T, 1, 3>::new(T::ONE, T::ZERO, T::ZERO);
+        let q = SMatrix::<T, 3, 3>::from_diagonal(&SVector::<T, 3>::new(
+            T::ZERO,
+            speed_noise,
+            torque_noise,
+        ));
+        let r = SMatrix::<T, 1, 1>::new(angle_noise);
+
+        Self::new(a, b, c, q, r)
+    }
+
+    /// Predict with the motor torque, then correct with the measured angle
+    pub fn update_mechanical(&mut self, torque: T, angle: T) -> Result<(), KalmanError> {
+        self.update(&SVector::<T, 1>::new(torque), &SVector::<T, 1>::new(angle))
+    }
+
+    /// Estimated angle
+    pub fn angle(&self) -> T {
+        self.x[0]
+    }
+
+    /// Estimated speed
+    pub fn speed(&self) -> T {
+        self.x[1]
+    }
+
+    /// Estimated load torque
+    pub fn load_torque(&self) -> T {
+        self.x[2]
+    }
+}
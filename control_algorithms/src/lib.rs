@@ -4,7 +4,27 @@
 #![warn(missing_docs)]
 #![warn(clippy::missing_docs_in_private_items)]
 
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod autotune;
+pub mod cascade;
+pub mod dead_time;
+#[cfg(feature = "std")]
+pub mod design;
+pub mod field_weakening;
 pub mod filters;
 pub mod foc;
+pub mod hfi;
+pub mod kalman;
+pub mod modulation;
+pub mod numeric;
 pub mod pid;
+pub mod pll;
+pub mod sensorless;
+pub mod six_step;
+pub mod state_space;
 pub mod svpwm;
+pub mod tuning;
+
+mod linalg;
@@ -0,0 +1,50 @@
+//! Small dense linear algebra helpers for fixed-size matrices
+
+use nalgebra::SMatrix;
+
+use crate::numeric::Real;
+
+/// Invert a square matrix using Gauss-Jordan elimination with partial pivoting
+pub(crate) fn invert<T: Real, const M: usize>(
+    matrix: SMatrix<T, M, M>,
+) -> Option<SMatrix<T, M, M>> {
+    let mut left = matrix;
+    let mut right = SMatrix::<T, M, M>::identity();
+
+    for column in 0..M {
+        // pick the largest pivot in this column
+        let mut pivot = column;
+        for row in column + 1..M {
+            if left[(row, column)].abs() > left[(pivot, column)].abs() {
+                pivot = row;
+            }
+        }
+
+        if left[(pivot, column)] == T::ZERO {
+            return None;
+        }
+
+        left.swap_rows(column, pivot);
+        right.swap_rows(column, pivot);
+
+        let scale = left[(column, column)];
+        for j in 0..M {
+            left[(column, j)] /= scale;
+            right[(column, j)] /= scale;
+        }
+
+        for row in 0..M {
+            if row != column {
+                let factor = left[(row, column)];
+                for j in 0..M {
+                    let l = left[(column, j)];
+                    let r = right[(column, j)];
+                    left[(row, j)] -= factor * l;
+                    right[(row, j)] -= factor * r;
+                }
+            }
+        }
+    }
+
+    Some(right)
+}
@@ -0,0 +1,233 @@
+//! Pulse width modulation strategies
+//!
+//! Every strategy turns a stationary frame reference into the same line-to-line voltages
+//! and only differs in the zero-sequence voltage added to all three phases. It sets the
+//! linear range, the harmonics and, for the discontinuous strategies, which phase is
+//! clamped to a rail and stops switching.
+
+use crate::foc::{inverse_clarke_transform, Vector2, Vector3};
+use crate::numeric::Real;
+use crate::svpwm::{check_inputs, svpwm, svpwm_overmodulation, SvpwmError};
+
+/// sqrt(3) / 2, cosine of 30 degrees
+const SQRT_3_2: f32 = 0.866_025_4;
+
+/// Turns a voltage reference into duty cycles
+pub trait Modulator<T: Real = f32> {
+    /// Duty cycles of phases a, b and c, between 0 and 1, for the reference `v_alpha`,
+    /// `v_beta` (amplitude invariant) and the bus voltage `v_dc`
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError>;
+}
+
+/// Phase voltages of a stationary frame reference, after checking the inputs
+fn phase_voltages<T: Real>(v_alpha: T, v_beta: T, v_dc: T) -> Result<Vector3<T>, SvpwmError> {
+    check_inputs(v_alpha, v_beta, v_dc)?;
+    Ok(inverse_clarke_transform(Vector2::new(v_alpha, v_beta)))
+}
+
+/// Highest and lowest phase voltage
+fn extremes<T: Real>(v_abc: Vector3<T>) -> (T, T) {
+    (
+        v_abc[0].max(v_abc[1]).max(v_abc[2]),
+        v_abc[0].min(v_abc[1]).min(v_abc[2]),
+    )
+}
+
+/// Duty cycles of the phase voltages with a zero-sequence voltage added, clamped to the
+/// range the inverter can produce
+fn duties<T: Real>(v_abc: Vector3<T>, zero_sequence: T, v_dc: T) -> [T; 3] {
+    let half = T::from_f32(0.5);
+    [v_abc[0], v_abc[1], v_abc[2]]
+        .map(|v| (half + (v + zero_sequence) / v_dc).clamp(T::ZERO, T::ONE))
+}
+
+/// Zero-sequence voltage that clamps one phase to a rail
+///
+/// The phase is the one with the largest magnitude in `selection`, clamped to the top
+/// rail if it is positive there and to the bottom rail otherwise.
+fn clamp_phase<T: Real>(v_abc: Vector3<T>, selection: Vector3<T>, v_dc: T) -> T {
+    let mut phase = 0;
+    for i in 1..3 {
+        if selection[i].abs() > selection[phase].abs() {
+            phase = i;
+        }
+    }
+
+    let rail = v_dc * T::from_f32(0.5);
+    if selection[phase] >= T::ZERO {
+        rail - v_abc[phase]
+    } else {
+        -rail - v_abc[phase]
+    }
+}
+
+/// Phase voltages of the reference rotated by +-30 degrees, used to shift the clamping
+/// intervals of DPWM0 and DPWM2
+fn rotated_phases<T: Real>(v_alpha: T, v_beta: T, sin: T) -> Vector3<T> {
+    let cos = T::from_f32(SQRT_3_2);
+    inverse_clarke_transform(Vector2::new(
+        cos * v_alpha - sin * v_beta,
+        sin * v_alpha + cos * v_beta,
+    ))
+}
+
+/// Sinusoidal PWM, no zero-sequence voltage
+///
+/// Linear up to `v_dc / 2`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sinusoidal;
+
+impl<T: Real> Modulator<T> for Sinusoidal {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        let v_abc = phase_voltages(v_alpha, v_beta, v_dc)?;
+        Ok(duties(v_abc, T::ZERO, v_dc))
+    }
+}
+
+/// Third harmonic injection, a sixth of the fundamental
+///
+/// Linear up to `v_dc / sqrt(3)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ThirdHarmonic;
+
+impl<T: Real> Modulator<T> for ThirdHarmonic {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        let v_abc = phase_voltages(v_alpha, v_beta, v_dc)?;
+
+        // -V cos(3 theta) / 6, with V cos(theta) = v_alpha
+        let magnitude_squared = v_alpha * v_alpha + v_beta * v_beta;
+        let zero_sequence = if magnitude_squared > T::ZERO {
+            v_alpha * T::from_f32(0.5)
+                - T::from_f32(2.0 / 3.0) * v_alpha * v_alpha * v_alpha / magnitude_squared
+        } else {
+            T::ZERO
+        };
+
+        Ok(duties(v_abc, zero_sequence, v_dc))
+    }
+}
+
+/// Min-max injection, centres the phase voltages in the bus
+///
+/// Gives the same duty cycles as [`SpaceVector`] without finding the sector. Linear up to
+/// `v_dc / sqrt(3)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MinMax;
+
+impl<T: Real> Modulator<T> for MinMax {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        let v_abc = phase_voltages(v_alpha, v_beta, v_dc)?;
+        let (max, min) = extremes(v_abc);
+        let zero_sequence = -(max + min) * T::from_f32(0.5);
+        Ok(duties(v_abc, zero_sequence, v_dc))
+    }
+}
+
+/// Conventional space vector PWM, see [`svpwm`]
+///
+/// Only the duty cycles are returned. Call [`svpwm_overmodulation`] directly to know the
+/// region and the achieved gain, e.g. to hold the current loop integrators.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpaceVector {
+    /// Whether to overmodulate up to six-step, see [`svpwm_overmodulation`]
+    overmodulation: bool,
+}
+
+impl SpaceVector {
+    /// Constructor, limited to the hexagon
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overmodulate references outside the linear range up to six-step
+    pub fn with_overmodulation(mut self) -> Self {
+        self.overmodulation = true;
+        self
+    }
+}
+
+impl<T: Real> Modulator<T> for SpaceVector {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        if self.overmodulation {
+            Ok(svpwm_overmodulation(v_alpha, v_beta, v_dc)?.pwm.duties)
+        } else {
+            Ok(svpwm(v_alpha, v_beta, v_dc)?.duties)
+        }
+    }
+}
+
+/// Discontinuous PWM clamping each phase for 60 degrees, 30 degrees before the peaks of
+/// its voltage
+///
+/// Suits loads where the current leads the voltage. Linear up to `v_dc / sqrt(3)`, like
+/// all the discontinuous strategies.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dpwm0;
+
+impl<T: Real> Modulator<T> for Dpwm0 {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        let v_abc = phase_voltages(v_alpha, v_beta, v_dc)?;
+        let selection = rotated_phases(v_alpha, v_beta, T::from_f32(0.5));
+        Ok(duties(v_abc, clamp_phase(v_abc, selection, v_dc), v_dc))
+    }
+}
+
+/// Discontinuous PWM clamping each phase for 60 degrees, centred on the peaks of its
+/// voltage
+///
+/// Suits resistive loads, where the switching losses are the highest at the voltage peaks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dpwm1;
+
+impl<T: Real> Modulator<T> for Dpwm1 {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        let v_abc = phase_voltages(v_alpha, v_beta, v_dc)?;
+        Ok(duties(v_abc, clamp_phase(v_abc, v_abc, v_dc), v_dc))
+    }
+}
+
+/// Discontinuous PWM clamping each phase for 60 degrees, 30 degrees after the peaks of
+/// its voltage
+///
+/// Suits inductive loads like motors, where the current lags the voltage.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dpwm2;
+
+impl<T: Real> Modulator<T> for Dpwm2 {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        let v_abc = phase_voltages(v_alpha, v_beta, v_dc)?;
+        let selection = rotated_phases(v_alpha, v_beta, T::from_f32(-0.5));
+        Ok(duties(v_abc, clamp_phase(v_abc, selection, v_dc), v_dc))
+    }
+}
+
+/// Discontinuous PWM keeping the lowest phase on the bottom rail
+///
+/// Each phase is clamped for 120 degrees, and the bootstrap capacitors are refreshed
+/// every period.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DpwmMin;
+
+impl<T: Real> Modulator<T> for DpwmMin {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        let v_abc = phase_voltages(v_alpha, v_beta, v_dc)?;
+        let (_, min) = extremes(v_abc);
+        let zero_sequence = -v_dc * T::from_f32(0.5) - min;
+        Ok(duties(v_abc, zero_sequence, v_dc))
+    }
+}
+
+/// Discontinuous PWM keeping the highest phase on the top rail
+///
+/// Each phase is clamped for 120 degrees.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DpwmMax;
+
+impl<T: Real> Modulator<T> for DpwmMax {
+    fn modulate(&self, v_alpha: T, v_beta: T, v_dc: T) -> Result<[T; 3], SvpwmError> {
+        let v_abc = phase_voltages(v_alpha, v_beta, v_dc)?;
+        let (max, _) = extremes(v_abc);
+        let zero_sequence = v_dc * T::from_f32(0.5) - max;
+        Ok(duties(v_abc, zero_sequence, v_dc))
+    }
+}
@@ -0,0 +1,389 @@
+//! Numeric types that the control algorithms are generic over
+//!
+//! [`Real`] covers everything the algorithms need apart from trigonometry, and is
+//! implemented for `f32`, `f64` and the saturating fixed-point types [`Q15`] and [`Q31`].
+//! [`Float`] adds functions of angles in radians, and is only implemented for the
+//! floating-point types, as the fixed-point types cannot represent angles past ±1 rad.
+//!
+//! Fixed-point values cover the range `[-1, 1)`, so signals and gains have to be scaled
+//! (e.g. to per-unit values) before they are used with [`Q15`] or [`Q31`]. All arithmetic
+//! saturates at the ends of the range instead of wrapping around.
+
+use core::fmt::Debug;
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use micromath::F32Ext;
+use nalgebra::{ClosedAdd, ClosedDiv, ClosedMul, ClosedSub, Scalar};
+use num_traits::{One, Zero};
+
+/// Real number that the control algorithms can be computed with
+pub trait Real:
+    Scalar
+    + Copy
+    + Default
+    + PartialOrd
+    + Zero
+    + One
+    + ClosedAdd
+    + ClosedSub
+    + ClosedMul
+    + ClosedDiv
+    + Neg<Output = Self>
+{
+    /// Zero
+    const ZERO: Self;
+    /// One, or the largest representable value below one for fixed-point types
+    ///
+    /// For [`Q15`] and [`Q31`] this is `MAX`, one LSB short of one, so it is not a
+    /// multiplicative identity: `ONE - x` and `x * ONE` can be off by one LSB.
+    const ONE: Self;
+
+    /// Convert from `f32`, saturating if the value is out of range
+    fn from_f32(value: f32) -> Self;
+
+    /// Convert to `f32`
+    fn to_f32(self) -> f32;
+
+    /// Absolute value
+    fn abs(self) -> Self;
+
+    /// Square root (zero for negative fixed-point values)
+    fn sqrt(self) -> Self;
+
+    /// Whether the value is neither infinite nor NaN, always true for fixed-point types
+    fn is_finite(self) -> bool {
+        true
+    }
+
+    /// Smaller of two values
+    fn min(self, other: Self) -> Self {
+        if other < self {
+            other
+        } else {
+            self
+        }
+    }
+
+    /// Larger of two values
+    fn max(self, other: Self) -> Self {
+        if other > self {
+            other
+        } else {
+            self
+        }
+    }
+
+    /// Restrict a value to `[min, max]`
+    fn clamp(self, min: Self, max: Self) -> Self {
+        self.max(min).min(max)
+    }
+}
+
+/// Floating-point number with functions of angles in radians
+pub trait Float: Real {
+    /// Sine
+    fn sin(self) -> Self;
+
+    /// Cosine
+    fn cos(self) -> Self;
+
+    /// Four quadrant arctangent of `self` (y) and `other` (x)
+    fn atan2(self, other: Self) -> Self;
+
+    /// Exponential function
+    fn exp(self) -> Self;
+
+    /// Nearest integer, rounding halfway cases away from zero
+    fn round(self) -> Self;
+}
+
+impl Real for f32 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn abs(self) -> Self {
+        F32Ext::abs(self)
+    }
+
+    fn sqrt(self) -> Self {
+        // the micromath estimate is only accurate to a few percent, two Newton
+        // steps bring it close to full precision
+        let mut root = F32Ext::sqrt(self);
+        if root > 0.0 {
+            root = 0.5 * (root + self / root);
+            root = 0.5 * (root + self / root);
+        }
+        root
+    }
+
+    fn is_finite(self) -> bool {
+        f32::is_finite(self)
+    }
+}
+
+impl Float for f32 {
+    fn sin(self) -> Self {
+        F32Ext::sin(self)
+    }
+
+    fn cos(self) -> Self {
+        F32Ext::cos(self)
+    }
+
+    fn atan2(self, other: Self) -> Self {
+        F32Ext::atan2(self, other)
+    }
+
+    fn exp(self) -> Self {
+        F32Ext::exp(self)
+    }
+
+    fn round(self) -> Self {
+        F32Ext::round(self)
+    }
+}
+
+impl Real for f64 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+
+    fn from_f32(value: f32) -> Self {
+        value as f64
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn abs(self) -> Self {
+        libm::fabs(self)
+    }
+
+    fn sqrt(self) -> Self {
+        libm::sqrt(self)
+    }
+
+    fn is_finite(self) -> bool {
+        f64::is_finite(self)
+    }
+}
+
+impl Float for f64 {
+    fn sin(self) -> Self {
+        libm::sin(self)
+    }
+
+    fn cos(self) -> Self {
+        libm::cos(self)
+    }
+
+    fn atan2(self, other: Self) -> Self {
+        libm::atan2(self, other)
+    }
+
+    fn exp(self) -> Self {
+        libm::exp(self)
+    }
+
+    fn round(self) -> Self {
+        libm::round(self)
+    }
+}
+
+/// Integer square root (largest value whose square is not greater than the input)
+fn isqrt(value: u64) -> u64 {
+    let mut remainder = value;
+    let mut root = 0;
+    let mut bit = 1 << 62;
+
+    while bit > value {
+        bit >>= 2;
+    }
+
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    root
+}
+
+/// Implement a saturating fixed-point type with the given storage, intermediate and
+/// number of fractional bits
+macro_rules! fixed_point {
+    ($(#[$attr:meta])* $name:ident, $bits:ty, $wide:ty, $frac:expr) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, defmt::Format)]
+        pub struct $name($bits);
+
+        impl $name {
+            /// Smallest value (-1)
+            pub const MIN: Self = Self(<$bits>::MIN);
+            /// Largest value (1 - 2^-n)
+            pub const MAX: Self = Self(<$bits>::MAX);
+            /// Smallest positive value
+            pub const EPSILON: Self = Self(1);
+
+            /// Create a value from its raw representation
+            pub const fn from_bits(bits: $bits) -> Self {
+                Self(bits)
+            }
+
+            /// Raw representation of the value
+            pub const fn to_bits(self) -> $bits {
+                self.0
+            }
+
+            /// Saturate an intermediate result to the storage range
+            fn saturate(value: $wide) -> Self {
+                Self(value.clamp(<$bits>::MIN as $wide, <$bits>::MAX as $wide) as $bits)
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                Self(self.0.saturating_add(rhs.0))
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self(self.0.saturating_sub(rhs.0))
+            }
+        }
+
+        impl Mul for $name {
+            type Output = Self;
+
+            fn mul(self, rhs: Self) -> Self {
+                // round to nearest
+                let product = self.0 as $wide * rhs.0 as $wide + (1 << ($frac - 1));
+                Self::saturate(product >> $frac)
+            }
+        }
+
+        impl Div for $name {
+            type Output = Self;
+
+            fn div(self, rhs: Self) -> Self {
+                if rhs.0 == 0 {
+                    // saturate towards the sign of the dividend
+                    return match self.0 {
+                        0 => Self(0),
+                        n if n > 0 => Self::MAX,
+                        _ => Self::MIN,
+                    };
+                }
+
+                Self::saturate(((self.0 as $wide) << $frac) / rhs.0 as $wide)
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                Self(self.0.saturating_neg())
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl MulAssign for $name {
+            fn mul_assign(&mut self, rhs: Self) {
+                *self = *self * rhs;
+            }
+        }
+
+        impl DivAssign for $name {
+            fn div_assign(&mut self, rhs: Self) {
+                *self = *self / rhs;
+            }
+        }
+
+        impl Zero for $name {
+            fn zero() -> Self {
+                Self(0)
+            }
+
+            fn is_zero(&self) -> bool {
+                self.0 == 0
+            }
+        }
+
+        impl One for $name {
+            fn one() -> Self {
+                Self::MAX
+            }
+        }
+
+        impl Real for $name {
+            const ZERO: Self = Self(0);
+            const ONE: Self = Self::MAX;
+
+            fn from_f32(value: f32) -> Self {
+                // float to integer casts saturate (and map NaN to zero)
+                Self(F32Ext::round(value * (1u64 << $frac) as f32) as $bits)
+            }
+
+            fn to_f32(self) -> f32 {
+                self.0 as f32 / (1u64 << $frac) as f32
+            }
+
+            fn abs(self) -> Self {
+                Self(self.0.saturating_abs())
+            }
+
+            fn sqrt(self) -> Self {
+                if self.0 <= 0 {
+                    return Self(0);
+                }
+
+                Self::saturate(isqrt((self.0 as u64) << $frac) as $wide)
+            }
+        }
+    };
+}
+
+fixed_point!(
+    /// Signed Q15 fixed-point number (16 bits, 15 fractional bits)
+    Q15,
+    i16,
+    i32,
+    15
+);
+
+fixed_point!(
+    /// Signed Q31 fixed-point number (32 bits, 31 fractional bits)
+    Q31,
+    i32,
+    i64,
+    31
+);
@@ -1,25 +1,115 @@
 //! Proportional-integral-derivative based control algorithm
 
+use crate::numeric::Real;
+
+/// Strategy used to keep the integrator from winding up while the output is saturated
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AntiWindup<T = f32> {
+    /// No anti-windup, apart from the optional integrator limit
+    #[default]
+    None,
+    /// Conditional integration: stop integrating while the output is saturated
+    /// and the error would push it further into saturation
+    Clamping,
+    /// Back-calculation: feed the difference between the saturated and the
+    /// unsaturated output back into the integrator, scaled by the tracking gain `k_t`
+    BackCalculation {
+        /// Tracking gain (inverse of the tracking time constant)
+        k_t: T,
+    },
+}
+
+/// Signal that the derivative term acts on
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DerivativeMode {
+    /// Differentiate the error (setpoint steps cause a derivative kick)
+    #[default]
+    Error,
+    /// Differentiate the negated measurement, which ignores setpoint changes
+    Measurement,
+}
+
+/// First-order low-pass filter applied to the derivative term
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DerivativeFilter<T = f32> {
+    /// Raw finite difference
+    #[default]
+    None,
+    /// Filter with a fixed time constant in seconds
+    TimeConstant(T),
+    /// Filter with a time constant of `k_d / (k_p * N)`, i.e. the derivative
+    /// gain is limited to `N` times the proportional gain at high frequencies
+    ///
+    /// Needs proportional gain. If `k_p` is later modified to zero, the derivative is
+    /// left unfiltered, use [`DerivativeFilter::TimeConstant`] for such controllers.
+    N(T),
+}
+
+/// Control signal produced by a controller update
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PIDOutput<T = f32> {
+    /// Control signal, limited to the output limits of the controller
+    pub value: T,
+    /// Whether the control signal had to be limited
+    pub saturated: bool,
+}
+
+/// Set of PID gains
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PIDGains<T = f32> {
+    /// Proportional constant
+    pub k_p: T,
+    /// Integral constant
+    pub k_i: T,
+    /// Derivative constant
+    pub k_d: T,
+}
+
+impl<T: Real> PIDGains<T> {
+    /// Convert the gains to another numeric type
+    pub fn cast<U: Real>(self) -> PIDGains<U> {
+        PIDGains {
+            k_p: U::from_f32(self.k_p.to_f32()),
+            k_i: U::from_f32(self.k_i.to_f32()),
+            k_d: U::from_f32(self.k_d.to_f32()),
+        }
+    }
+}
+
 /// A simple implementation of the standard PID controller
 #[derive(Default)]
-pub struct PIDController {
+pub struct PIDController<T: Real = f32> {
     /// Proportional constant
-    pub k_p: f32,
-    /// Integral constant     
-    pub k_i: f32,
+    pub k_p: T,
+    /// Integral constant
+    pub k_i: T,
     /// Derivative constant
-    pub k_d: f32,
-    /// Previous error value
-    previous_error: f32,
+    pub k_d: T,
+    /// Previous value of the differentiated signal (error or negated measurement)
+    previous_derivative_input: T,
+    /// Filtered derivative component
+    derivative: T,
+    /// Previous value of the proportional signal (`b * input - measurement`)
+    previous_proportional_input: T,
     /// Accumulated error
-    accumulated_error: f32,
+    accumulated_error: T,
     /// Integrator limit
-    limit: Option<f32>,
+    limit: Option<T>,
+    /// Output limits (minimum, maximum)
+    output_limits: Option<(T, T)>,
+    /// Anti-windup strategy
+    anti_windup: AntiWindup<T>,
+    /// Signal used by the derivative term
+    derivative_mode: DerivativeMode,
+    /// Derivative filter
+    derivative_filter: DerivativeFilter<T>,
+    /// Whether the integrator is held at its current value
+    integrator_frozen: bool,
 }
 
-impl PIDController {
+impl<T: Real> PIDController<T> {
     /// Constructor with field values
-    pub fn new(k_p: f32, k_i: f32, k_d: f32, limit: Option<f32>) -> Self {
+    pub fn new(k_p: T, k_i: T, k_d: T, limit: Option<T>) -> Self {
         Self {
             k_p,
             k_i,
@@ -29,37 +119,508 @@ impl PIDController {
         }
     }
 
+    /// Constructor from a set of gains
+    pub fn from_gains(gains: PIDGains<T>, limit: Option<T>) -> Self {
+        Self::new(gains.k_p, gains.k_i, gains.k_d, limit)
+    }
+
+    /// Limit the controller output to `[min, max]`
+    pub fn with_output_limits(mut self, min: T, max: T) -> Self {
+        assert!(
+            min <= max,
+            "Minimum output cannot be greater than maximum output!"
+        );
+        self.output_limits = Some((min, max));
+        self
+    }
+
+    /// Select the anti-windup strategy used while the output is saturated
+    pub fn with_anti_windup(mut self, anti_windup: AntiWindup<T>) -> Self {
+        self.anti_windup = anti_windup;
+        self
+    }
+
+    /// Select which signal the derivative term acts on
+    pub fn with_derivative_mode(mut self, derivative_mode: DerivativeMode) -> Self {
+        self.derivative_mode = derivative_mode;
+        self
+    }
+
+    /// Low-pass filter the derivative term
+    pub fn with_derivative_filter(mut self, derivative_filter: DerivativeFilter<T>) -> Self {
+        if let DerivativeFilter::N(n) = derivative_filter {
+            assert!(
+                self.k_p != T::ZERO,
+                "Derivative filter N needs a proportional gain, use a time constant instead!"
+            );
+            assert!(n > T::ZERO, "Derivative filter N has to be positive!");
+        }
+        self.derivative_filter = derivative_filter;
+        self
+    }
+
+    /// Time constant of the derivative filter
+    fn derivative_time_constant(&self) -> T {
+        match self.derivative_filter {
+            DerivativeFilter::None => T::ZERO,
+            DerivativeFilter::TimeConstant(t_f) => t_f,
+            DerivativeFilter::N(n) if self.k_p != T::ZERO && n > T::ZERO => {
+                self.k_d / (self.k_p * n)
+            }
+            DerivativeFilter::N(_) => T::ZERO,
+        }
+    }
+
     /// Modify controller parameters
-    pub fn modify(&mut self, k_p: f32, k_i: f32, k_d: f32) {
+    ///
+    /// The integrator is adjusted so that the proportional and integral components of
+    /// the output add up to the same value for the last error, which avoids a jump in
+    /// the output when `k_p` or `k_i` changes. Without integral gain (`k_i == 0`) there
+    /// is nothing to absorb the difference, so a change of `k_p` still steps the output.
+    pub fn modify(&mut self, k_p: T, k_i: T, k_d: T) {
+        if k_i != T::ZERO {
+            self.accumulated_error = (self.k_i * self.accumulated_error
+                + (self.k_p - k_p) * self.previous_proportional_input)
+                / k_i;
+            self.clamp_integrator();
+        }
+
         self.k_p = k_p;
         self.k_i = k_i;
         self.k_d = k_d;
     }
 
+    /// Preload the controller so that the next output continues smoothly from `output`
+    ///
+    /// Use this when taking over from another controller or from manual control:
+    /// the integrator absorbs the difference between `output` and the proportional
+    /// component, and the derivative history is set to the current signals. Without
+    /// integral gain (`k_i == 0`) there is nothing to absorb the difference, so the
+    /// output continues from the proportional component instead.
+    pub fn initialize(&mut self, output: T, input: T, measurement: T) {
+        self.preload(output, input, measurement, T::ONE, T::ONE, T::ZERO);
+    }
+
+    /// Clear all internal states of the controller, and resume integrating
+    pub fn reset(&mut self) {
+        self.previous_derivative_input = T::ZERO;
+        self.derivative = T::ZERO;
+        self.previous_proportional_input = T::ZERO;
+        self.accumulated_error = T::ZERO;
+        self.integrator_frozen = false;
+    }
+
+    /// Hold the integrator at its current value (`true`) or resume integrating (`false`)
+    pub fn freeze_integrator(&mut self, frozen: bool) {
+        self.integrator_frozen = frozen;
+    }
+
+    /// Integral component of the output
+    pub fn integral(&self) -> T {
+        self.k_i * self.accumulated_error
+    }
+
+    /// Apply the integrator limit
+    fn clamp_integrator(&mut self) {
+        if let Some(limit) = self.limit {
+            self.accumulated_error = self.accumulated_error.clamp(-limit, limit);
+        }
+    }
+
+    /// Set the internal states so that the controller produces `output` for the given signals
+    fn preload(&mut self, output: T, input: T, measurement: T, b: T, c: T, feedforward: T) {
+        self.previous_proportional_input = b * input - measurement;
+        let p = self.k_p * self.previous_proportional_input;
+
+        if self.k_i != T::ZERO {
+            self.accumulated_error = (output - p - feedforward) / self.k_i;
+            self.clamp_integrator();
+        }
+
+        self.previous_derivative_input = match self.derivative_mode {
+            DerivativeMode::Error => c * input - measurement,
+            DerivativeMode::Measurement => -measurement,
+        };
+        self.derivative = T::ZERO;
+    }
+
     /// Update internal states of controller, and return control signal
-    pub fn output(&mut self, input: f32, measurement: f32, delta_t: f32) -> f32 {
+    pub fn output(&mut self, input: T, measurement: T, delta_t: T) -> PIDOutput<T> {
+        self.update(input, measurement, T::ONE, T::ONE, T::ZERO, delta_t)
+    }
+
+    /// Controller update with setpoint weights for the proportional (`b`) and
+    /// derivative (`c`) paths and an additive feedforward term
+    fn update(
+        &mut self,
+        input: T,
+        measurement: T,
+        b: T,
+        c: T,
+        feedforward: T,
+        delta_t: T,
+    ) -> PIDOutput<T> {
         // Calculate current error
 
         let error = input - measurement;
 
+        // Keep the previous integrator state in case integration has to be undone
+        let previous_accumulated_error = self.accumulated_error;
+
         // Update integral term
-        self.accumulated_error += error * delta_t;
+        if !self.integrator_frozen {
+            self.accumulated_error += error * delta_t;
+        }
 
         // Prevent integral wind-up
-        if let Some(limit) = self.limit {
-            self.accumulated_error = self.accumulated_error.clamp(-limit, limit);
-        }
+        self.clamp_integrator();
 
-        // Proportional component (constant * error)
-        let p = self.k_p * error;
+        // Proportional component (constant * weighted error)
+        self.previous_proportional_input = b * input - measurement;
+        let p = self.k_p * self.previous_proportional_input;
         // Integral component (constant * integral(error, dt))
         let i = self.k_i * self.accumulated_error;
-        // Derivative component (constant * d/dt(error))
-        let d = self.k_d * (error - self.previous_error) / delta_t;
-        // Update previous error value to be used in next iteration
-        self.previous_error = error;
+        // Derivative component (constant * d/dt(error)), filtered using backward Euler:
+        // d[k] = (t_f * d[k-1] + k_d * (x[k] - x[k-1])) / (t_f + dt)
+        let derivative_input = match self.derivative_mode {
+            DerivativeMode::Error => c * input - measurement,
+            DerivativeMode::Measurement => -measurement,
+        };
+        let t_f = self.derivative_time_constant();
+        self.derivative = (t_f * self.derivative
+            + self.k_d * (derivative_input - self.previous_derivative_input))
+            / (t_f + delta_t);
+        let d = self.derivative;
+        // Update previous value to be used in next iteration
+        self.previous_derivative_input = derivative_input;
+
+        // Saturate the controller output
+        let unsaturated = p + i + d + feedforward;
+        let value = match self.output_limits {
+            Some((min, max)) => unsaturated.clamp(min, max),
+            None => unsaturated,
+        };
+
+        // Apply anti-windup correction to the integrator
+        match self.anti_windup {
+            AntiWindup::None => (),
+            AntiWindup::Clamping => {
+                // only stop integrating if the error drives the output further into saturation
+                if (unsaturated > value && error > T::ZERO)
+                    || (unsaturated < value && error < T::ZERO)
+                {
+                    self.accumulated_error = previous_accumulated_error;
+                }
+            }
+            AntiWindup::BackCalculation { k_t } => {
+                if self.k_i != T::ZERO && !self.integrator_frozen {
+                    self.accumulated_error += k_t * (value - unsaturated) / self.k_i * delta_t;
+                    self.clamp_integrator();
+                }
+            }
+        }
 
         // Return controller output
-        p + i + d
+        PIDOutput {
+            value,
+            saturated: value != unsaturated,
+        }
+    }
+}
+
+/// Velocity, acceleration and static friction feedforward gains
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Feedforward<T = f32> {
+    /// Velocity gain
+    pub k_v: T,
+    /// Acceleration gain
+    pub k_a: T,
+    /// Static friction compensation, applied in the direction of motion
+    pub k_s: T,
+}
+
+impl<T: Real> Feedforward<T> {
+    /// Constructor with field values
+    pub fn new(k_v: T, k_a: T, k_s: T) -> Self {
+        Self { k_v, k_a, k_s }
+    }
+
+    /// Feedforward contribution for the given reference velocity and acceleration
+    pub fn output(&self, velocity: T, acceleration: T) -> T {
+        let direction = if velocity > T::ZERO {
+            T::ONE
+        } else if velocity < T::ZERO {
+            -T::ONE
+        } else {
+            T::ZERO
+        };
+
+        self.k_v * velocity + self.k_a * acceleration + self.k_s * direction
+    }
+}
+
+/// Two-degree-of-freedom PID controller
+///
+/// The proportional and derivative paths act on `b * input - measurement` and
+/// `c * input - measurement` respectively, while the integral path always acts on
+/// the full error. This shapes the response to setpoint changes without changing
+/// the disturbance rejection of the underlying [`PIDController`]. If the controller
+/// differentiates the measurement ([`DerivativeMode::Measurement`]), `c` is ignored.
+#[derive(Default)]
+pub struct TwoDOFController<T: Real = f32> {
+    /// Underlying PID controller
+    pub controller: PIDController<T>,
+    /// Proportional setpoint weight
+    pub b: T,
+    /// Derivative setpoint weight
+    pub c: T,
+    /// Feedforward gains
+    pub feedforward: Feedforward<T>,
+}
+
+impl<T: Real> TwoDOFController<T> {
+    /// Constructor with field values
+    pub fn new(controller: PIDController<T>, b: T, c: T) -> Self {
+        Self {
+            controller,
+            b,
+            c,
+            ..Default::default()
+        }
+    }
+
+    /// Add velocity, acceleration and static friction feedforward
+    pub fn with_feedforward(mut self, feedforward: Feedforward<T>) -> Self {
+        self.feedforward = feedforward;
+        self
+    }
+
+    /// Preload the controller so that the next output continues smoothly from `output`
+    ///
+    /// See [`PIDController::initialize`].
+    pub fn initialize(
+        &mut self,
+        output: T,
+        input: T,
+        measurement: T,
+        velocity: T,
+        acceleration: T,
+    ) {
+        let feedforward = self.feedforward.output(velocity, acceleration);
+
+        self.controller
+            .preload(output, input, measurement, self.b, self.c, feedforward);
+    }
+
+    /// Update internal states of controller, and return control signal
+    ///
+    /// `velocity` and `acceleration` are the derivatives of the reference, used by the feedforward.
+    pub fn output(
+        &mut self,
+        input: T,
+        measurement: T,
+        velocity: T,
+        acceleration: T,
+        delta_t: T,
+    ) -> PIDOutput<T> {
+        let feedforward = self.feedforward.output(velocity, acceleration);
+
+        self.controller
+            .update(input, measurement, self.b, self.c, feedforward, delta_t)
+    }
+}
+
+/// Operating mode of a controller that supports manual output
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ControlMode {
+    /// Output is computed by the controller
+    #[default]
+    Automatic,
+    /// Output is held at a value set by the user
+    Manual,
+}
+
+/// Incremental (velocity-form) PID controller
+///
+/// Each update computes the change in output, which is added to the previous output
+/// and clamped to the output limits. Because the output itself is the only
+/// integrating state, it cannot wind up, and switching between manual and automatic
+/// mode is bumpless.
+#[derive(Default)]
+pub struct IncrementalPIDController<T: Real = f32> {
+    /// Proportional constant
+    pub k_p: T,
+    /// Integral constant
+    pub k_i: T,
+    /// Derivative constant
+    pub k_d: T,
+    /// Previous error value
+    previous_error: T,
+    /// Previous value of the differentiated signal (error or negated measurement)
+    previous_derivative_input: T,
+    /// Previous derivative component
+    previous_derivative: T,
+    /// Current output
+    output: T,
+    /// Output limits (minimum, maximum)
+    output_limits: Option<(T, T)>,
+    /// Signal used by the derivative term
+    derivative_mode: DerivativeMode,
+    /// Operating mode
+    mode: ControlMode,
+}
+
+impl<T: Real> IncrementalPIDController<T> {
+    /// Constructor with field values
+    pub fn new(k_p: T, k_i: T, k_d: T) -> Self {
+        Self {
+            k_p,
+            k_i,
+            k_d,
+            ..Default::default()
+        }
+    }
+
+    /// Limit the controller output to `[min, max]`
+    pub fn with_output_limits(mut self, min: T, max: T) -> Self {
+        assert!(
+            min <= max,
+            "Minimum output cannot be greater than maximum output!"
+        );
+        self.output_limits = Some((min, max));
+        self
+    }
+
+    /// Select which signal the derivative term acts on
+    pub fn with_derivative_mode(mut self, derivative_mode: DerivativeMode) -> Self {
+        self.derivative_mode = derivative_mode;
+        self
+    }
+
+    /// Modify controller parameters
+    pub fn modify(&mut self, k_p: T, k_i: T, k_d: T) {
+        self.k_p = k_p;
+        self.k_i = k_i;
+        self.k_d = k_d;
+    }
+
+    /// Switch to manual mode and hold the output at `output`
+    pub fn set_manual(&mut self, output: T) {
+        self.mode = ControlMode::Manual;
+        self.output = self.saturate(output);
+    }
+
+    /// Switch to automatic mode, continuing from the current output
+    pub fn set_automatic(&mut self) {
+        self.mode = ControlMode::Automatic;
+    }
+
+    /// Current operating mode
+    pub fn mode(&self) -> ControlMode {
+        self.mode
+    }
+
+    /// Clamp a value to the output limits
+    fn saturate(&self, value: T) -> T {
+        match self.output_limits {
+            Some((min, max)) => value.clamp(min, max),
+            None => value,
+        }
+    }
+
+    /// Update internal states of controller, and return control signal
+    pub fn output(&mut self, input: T, measurement: T, delta_t: T) -> PIDOutput<T> {
+        // Calculate current error
+        let error = input - measurement;
+
+        // Derivative component, differentiated once more below
+        let derivative_input = match self.derivative_mode {
+            DerivativeMode::Error => error,
+            DerivativeMode::Measurement => -measurement,
+        };
+        let derivative = self.k_d * (derivative_input - self.previous_derivative_input) / delta_t;
+
+        // Change in output
+        let delta_p = self.k_p * (error - self.previous_error);
+        let delta_i = self.k_i * error * delta_t;
+        let delta_d = derivative - self.previous_derivative;
+
+        // Update previous values to be used in next iteration
+        self.previous_error = error;
+        self.previous_derivative_input = derivative_input;
+        self.previous_derivative = derivative;
+
+        if self.mode == ControlMode::Manual {
+            return PIDOutput {
+                value: self.output,
+                saturated: false,
+            };
+        }
+
+        let unsaturated = self.output + delta_p + delta_i + delta_d;
+        self.output = self.saturate(unsaturated);
+
+        PIDOutput {
+            value: self.output,
+            saturated: self.output != unsaturated,
+        }
+    }
+}
+
+/// Gain schedule interpolating PID gains from a table of breakpoints
+///
+/// Gains are linearly interpolated on an operating variable such as speed, bus voltage
+/// or current, and held constant outside of the range of the table.
+pub struct GainSchedule<const N: usize, T: Real = f32> {
+    /// Values of the operating variable, strictly increasing
+    breakpoints: [T; N],
+    /// Gains at each breakpoint
+    gains: [PIDGains<T>; N],
+}
+
+impl<const N: usize, T: Real> GainSchedule<N, T> {
+    /// Constructor with field values
+    pub fn new(breakpoints: [T; N], gains: [PIDGains<T>; N]) -> Self {
+        assert!(N > 0, "Gain schedule needs at least one breakpoint!");
+        assert!(
+            breakpoints.windows(2).all(|pair| pair[0] < pair[1]),
+            "Breakpoints must be strictly increasing!"
+        );
+
+        Self { breakpoints, gains }
+    }
+
+    /// Interpolate the gains at the given value of the operating variable
+    pub fn gains(&self, operating_point: T) -> PIDGains<T> {
+        if operating_point <= self.breakpoints[0] {
+            return self.gains[0];
+        }
+
+        for i in 1..N {
+            if operating_point <= self.breakpoints[i] {
+                let (x_0, x_1) = (self.breakpoints[i - 1], self.breakpoints[i]);
+                let (g_0, g_1) = (self.gains[i - 1], self.gains[i]);
+
+                let fraction = (operating_point - x_0) / (x_1 - x_0);
+                let interpolate = |y_0: T, y_1: T| y_0 + (y_1 - y_0) * fraction;
+
+                return PIDGains {
+                    k_p: interpolate(g_0.k_p, g_1.k_p),
+                    k_i: interpolate(g_0.k_i, g_1.k_i),
+                    k_d: interpolate(g_0.k_d, g_1.k_d),
+                };
+            }
+        }
+
+        self.gains[N - 1]
+    }
+
+    /// Update the gains of a controller for the given value of the operating variable
+    ///
+    /// Uses [`PIDController::modify`], so the output stays continuous as long as the
+    /// controller has integral gain.
+    pub fn apply(&self, controller: &mut PIDController<T>, operating_point: T) {
+        let gains = self.gains(operating_point);
+        controller.modify(gains.k_p, gains.k_i, gains.k_d);
     }
 }
@@ -0,0 +1,166 @@
+//! Phase-locked loop for tracking the angle and speed of sin/cos signals
+
+use core::f32::consts::PI;
+
+use crate::numeric::Float;
+
+/// Output of the phase-locked loop
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AngleEstimate<T = f32> {
+    /// Angle wrapped to (-π, π]
+    pub angle: T,
+    /// Angle including all completed turns
+    pub unwrapped_angle: T,
+    /// Angular speed in rad/s
+    pub speed: T,
+    /// Angular acceleration in rad/s^2 (zero unless enabled)
+    pub acceleration: T,
+}
+
+/// Phase-locked loop tracking observer
+///
+/// The phase detector compares the sin/cos signals with the estimated angle,
+/// normalized by the signal amplitude, and a PI loop (with an optional extra
+/// integrator for the acceleration) drives the error to zero. There is no phase lag
+/// for constant speed (or, with acceleration enabled, constant acceleration).
+///
+/// For `alpha/beta` signals, pass `beta` as the sine and `alpha` as the cosine.
+pub struct PhaseLockedLoop<T: Float = f32> {
+    /// Bandwidth of the loop in rad/s
+    bandwidth: T,
+    /// Proportional gain
+    k_p: T,
+    /// Integral gain (speed)
+    k_i: T,
+    /// Double integral gain (acceleration), zero if disabled
+    k_a: T,
+    /// Estimated angle, wrapped to (-π, π]
+    angle: T,
+    /// Completed turns
+    turns: i32,
+    /// Estimated speed
+    speed: T,
+    /// Estimated acceleration
+    acceleration: T,
+}
+
+impl<T: Float> PhaseLockedLoop<T> {
+    /// Create a type-2 loop (angle and speed) with critical damping
+    pub fn new(bandwidth: T) -> Self {
+        Self {
+            bandwidth,
+            k_p: T::from_f32(2.0) * bandwidth,
+            k_i: bandwidth * bandwidth,
+            k_a: T::ZERO,
+            angle: T::ZERO,
+            turns: 0,
+            speed: T::ZERO,
+            acceleration: T::ZERO,
+        }
+    }
+
+    /// Also estimate the acceleration, using a type-3 loop with all poles at the bandwidth
+    pub fn with_acceleration(mut self) -> Self {
+        let bandwidth = self.bandwidth;
+        self.k_p = T::from_f32(3.0) * bandwidth;
+        self.k_i = T::from_f32(3.0) * bandwidth * bandwidth;
+        self.k_a = bandwidth * bandwidth * bandwidth;
+        self
+    }
+
+    /// Lock on to the angle of the given signals immediately, at zero speed
+    pub fn initialize(&mut self, sin: T, cos: T) {
+        self.angle = sin.atan2(cos);
+        self.turns = 0;
+        self.speed = T::ZERO;
+        self.acceleration = T::ZERO;
+    }
+
+    /// Current estimate
+    pub fn estimate(&self) -> AngleEstimate<T> {
+        let turn = T::from_f32(2.0 * PI);
+
+        AngleEstimate {
+            angle: self.angle,
+            unwrapped_angle: T::from_f32(self.turns as f32) * turn + self.angle,
+            speed: self.speed,
+            acceleration: self.acceleration,
+        }
+    }
+
+    /// Completed turns
+    pub fn turns(&self) -> i32 {
+        self.turns
+    }
+
+    /// Feed new sin/cos samples and return the updated estimate
+    pub fn update(&mut self, sin: T, cos: T, delta_t: T) -> AngleEstimate<T> {
+        // phase detector: amplitude * sin(angle - estimated angle)
+        let mut error = sin * self.angle.cos() - cos * self.angle.sin();
+
+        let amplitude = (sin * sin + cos * cos).sqrt();
+        if amplitude > T::ZERO {
+            error /= amplitude;
+        }
+
+        self.track(error, delta_t)
+    }
+
+    /// Feed the phase error `sin(angle - estimated angle)` from an external phase
+    /// detector and return the updated estimate
+    ///
+    /// An update that would make the estimate infinite, NaN or too large to wrap (e.g.
+    /// from a bad time step) is ignored.
+    pub fn track(&mut self, error: T, delta_t: T) -> AngleEstimate<T> {
+        // loop filter
+        let acceleration = self.acceleration + self.k_a * error * delta_t;
+        let speed = self.speed + (acceleration + self.k_i * error) * delta_t;
+        let angle = self.angle + (speed + self.k_p * error) * delta_t;
+
+        if !acceleration.is_finite() || !speed.is_finite() {
+            return self.estimate();
+        }
+
+        // wrap the angle and count turns
+        let Some((angle, turns)) = wrap_angle(angle) else {
+            return self.estimate();
+        };
+
+        self.acceleration = acceleration;
+        self.speed = speed;
+        self.angle = angle;
+        self.turns = self.turns.saturating_add(turns);
+
+        self.estimate()
+    }
+}
+
+/// Wrap an angle to (-π, π] in a single step, returns it with the number of turns taken
+/// off, or `None` if it is infinite, NaN or too large to be wrapped precisely
+pub(crate) fn wrap_angle<T: Float>(angle: T) -> Option<(T, i32)> {
+    if !angle.is_finite() {
+        return None;
+    }
+
+    let half_turn = T::from_f32(PI);
+    let turn = half_turn + half_turn;
+
+    let turns = (angle / turn).round();
+    let mut angle = angle - turns * turn;
+    let mut turns = turns.to_f32() as i32;
+
+    if angle.abs() > turn {
+        return None;
+    }
+
+    // rounding can leave the angle just outside the range, e.g. at -π for halfway cases
+    if angle > half_turn {
+        angle -= turn;
+        turns = turns.saturating_add(1);
+    } else if angle <= -half_turn {
+        angle += turn;
+        turns = turns.saturating_sub(1);
+    }
+
+    Some((angle, turns))
+}
@@ -0,0 +1,340 @@
+//! Sensorless rotor angle and speed estimation from the back-EMF
+//!
+//! A nonlinear flux observer estimates the rotor flux from the `alpha/beta` voltages
+//! and currents, and a [`PhaseLockedLoop`] tracks its angle and speed. The back-EMF
+//! vanishes at standstill, so the motor is started with an open-loop I/f ramp and
+//! handed over to the observer once its estimate agrees with the ramp.
+
+use core::f32::consts::PI;
+
+use crate::foc::Vector2;
+use crate::numeric::Float;
+use crate::pll::{wrap_angle, AngleEstimate, PhaseLockedLoop};
+
+/// Nonlinear flux observer (Ortega et al.)
+///
+/// The stator flux is integrated from `v - R i`, and the rotor flux `flux - L i` is
+/// pulled towards a circle with the radius of the permanent magnet flux linkage, which
+/// removes the integrator drift without the phase lag of a low-pass filter.
+pub struct FluxObserver<T: Float = f32> {
+    /// Phase resistance in ohm
+    resistance: T,
+    /// Phase inductance in henry
+    inductance: T,
+    /// Permanent magnet flux linkage in weber
+    flux_linkage: T,
+    /// Observer gain
+    gain: T,
+    /// Estimated stator flux
+    flux: Vector2<T>,
+    /// Loop tracking the angle of the rotor flux
+    pll: PhaseLockedLoop<T>,
+}
+
+impl<T: Float> FluxObserver<T> {
+    /// Constructor with motor parameters, observer gain and the bandwidth of the speed PLL
+    ///
+    /// A good starting point for the gain is a few times `bandwidth / flux_linkage^2`.
+    pub fn new(resistance: T, inductance: T, flux_linkage: T, gain: T, pll_bandwidth: T) -> Self {
+        Self {
+            resistance,
+            inductance,
+            flux_linkage,
+            gain,
+            flux: Vector2::new(flux_linkage, T::ZERO),
+            pll: PhaseLockedLoop::new(pll_bandwidth),
+        }
+    }
+
+    /// Restart from zero angle and speed
+    pub fn reset(&mut self) {
+        self.flux = Vector2::new(self.flux_linkage, T::ZERO);
+        self.pll.initialize(T::ZERO, T::ONE);
+    }
+
+    /// Estimated rotor (magnet) flux for the given currents
+    fn rotor_flux(&self, current: Vector2<T>) -> Vector2<T> {
+        self.flux - current * self.inductance
+    }
+
+    /// Current estimate
+    pub fn estimate(&self) -> AngleEstimate<T> {
+        self.pll.estimate()
+    }
+
+    /// Feed new `alpha/beta` voltages and currents and return the updated estimate
+    pub fn update(
+        &mut self,
+        voltage: Vector2<T>,
+        current: Vector2<T>,
+        delta_t: T,
+    ) -> AngleEstimate<T> {
+        let rotor_flux = self.rotor_flux(current);
+        let half = T::from_f32(0.5);
+        let error = self.flux_linkage * self.flux_linkage - rotor_flux.dot(&rotor_flux);
+
+        let back_emf = voltage - current * self.resistance;
+        self.flux += (back_emf + rotor_flux * (half * self.gain * error)) * delta_t;
+
+        let rotor_flux = self.rotor_flux(current);
+        self.pll.update(rotor_flux[1], rotor_flux[0], delta_t)
+    }
+}
+
+/// Open-loop I/f startup: a fixed current vector rotating at a ramped frequency
+pub struct IfStartup<T: Float = f32> {
+    /// Current amplitude to command on the q axis
+    current: T,
+    /// Speed ramp in rad/s^2 (electrical)
+    acceleration: T,
+    /// Speed at which the ramp stops in rad/s (electrical)
+    final_speed: T,
+    /// Commanded angle, wrapped to (-π, π]
+    angle: T,
+    /// Commanded speed
+    speed: T,
+}
+
+impl<T: Float> IfStartup<T> {
+    /// Constructor with field values, starting at rest
+    ///
+    /// A negative `final_speed` starts the motor backwards.
+    pub fn new(current: T, acceleration: T, final_speed: T) -> Self {
+        assert!(
+            final_speed != T::ZERO,
+            "Final speed cannot be zero, there would be no back-EMF to hand over to!"
+        );
+
+        Self {
+            current,
+            acceleration: acceleration.abs(),
+            final_speed,
+            angle: T::ZERO,
+            speed: T::ZERO,
+        }
+    }
+
+    /// Restart the ramp from the given angle at rest, or from zero if it is not finite
+    pub fn reset(&mut self, angle: T) {
+        self.angle = wrap_angle(angle).map_or(T::ZERO, |(angle, _)| angle);
+        self.speed = T::ZERO;
+    }
+
+    /// Current amplitude to command on the q axis of the open-loop angle
+    pub fn current(&self) -> T {
+        self.current
+    }
+
+    /// Commanded angle
+    pub fn angle(&self) -> T {
+        self.angle
+    }
+
+    /// Commanded speed
+    pub fn speed(&self) -> T {
+        self.speed
+    }
+
+    /// Whether the ramp has reached its final speed
+    pub fn finished(&self) -> bool {
+        self.speed == self.final_speed
+    }
+
+    /// Advance the ramp and return the commanded angle
+    ///
+    /// A time step that would make the angle infinite, NaN or too large to wrap leaves the
+    /// ramp where it was.
+    pub fn update(&mut self, delta_t: T) -> T {
+        let step = self.acceleration * delta_t;
+        let speed = if self.final_speed >= T::ZERO {
+            (self.speed + step).min(self.final_speed)
+        } else {
+            (self.speed - step).max(self.final_speed)
+        };
+
+        if let Some((angle, _)) = wrap_angle(self.angle + speed * delta_t) {
+            self.speed = speed;
+            self.angle = angle;
+        }
+        self.angle
+    }
+}
+
+/// Conditions for handing over from the open-loop ramp to the observer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Handover<T = f32> {
+    /// Largest difference between observer and ramp speed, relative to the ramp speed
+    pub speed_tolerance: T,
+    /// Largest difference between observer and ramp angle in rad
+    ///
+    /// The ramp leads or trails the rotor by the load angle, which stays below π/2 as
+    /// long as the rotor follows the ramp.
+    pub angle_tolerance: T,
+    /// Number of consecutive samples that have to meet both tolerances
+    pub samples: u32,
+    /// Rate in rad/s at which the angle difference is removed after the handover
+    pub transition_rate: T,
+    /// Longest time in seconds the observer may take to meet the conditions once the ramp
+    /// has reached its final speed, before the start is reported as failed
+    pub timeout: T,
+}
+
+impl<T: Float> Default for Handover<T> {
+    fn default() -> Self {
+        Self {
+            speed_tolerance: T::from_f32(0.1),
+            angle_tolerance: T::from_f32(PI / 2.0),
+            samples: 100,
+            transition_rate: T::from_f32(50.0),
+            timeout: T::from_f32(2.0),
+        }
+    }
+}
+
+/// Source of the angle used by the [`SensorlessEstimator`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, defmt::Format)]
+pub enum SensorlessMode {
+    /// The I/f ramp drives the angle, command [`IfStartup::current`] on the q axis
+    #[default]
+    OpenLoop,
+    /// The observer drives the angle
+    ClosedLoop,
+    /// The observer did not agree with the ramp within the handover timeout, the start has
+    /// to be aborted and the estimator reset
+    Failed,
+}
+
+/// Output of the [`SensorlessEstimator`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SensorlessEstimate<T = f32> {
+    /// Electrical angle to use for the park transforms, wrapped to (-π, π]
+    pub angle: T,
+    /// Electrical speed in rad/s
+    pub speed: T,
+    /// Source of the angle
+    pub mode: SensorlessMode,
+}
+
+/// I/f startup followed by the flux observer
+///
+/// The observer runs during the ramp as well. Once the ramp has reached its final
+/// speed and the observer agrees with it for long enough, the angle is taken from the
+/// observer. The angle difference at that moment (mostly the load angle of the ramp)
+/// is removed gradually so that the current references do not jump. If that does not
+/// happen within the handover timeout, the estimator stops in [`SensorlessMode::Failed`].
+pub struct SensorlessEstimator<T: Float = f32> {
+    /// Back-EMF observer
+    pub observer: FluxObserver<T>,
+    /// Open-loop startup ramp
+    pub startup: IfStartup<T>,
+    /// Handover conditions
+    handover: Handover<T>,
+    /// Consecutive samples that met the handover conditions
+    count: u32,
+    /// Time spent at the final speed of the ramp without handing over
+    elapsed: T,
+    /// Source of the angle
+    mode: SensorlessMode,
+    /// Remaining angle difference between the output and the observer
+    offset: T,
+}
+
+impl<T: Float> SensorlessEstimator<T> {
+    /// Constructor with field values, starting in open loop
+    pub fn new(observer: FluxObserver<T>, startup: IfStartup<T>) -> Self {
+        Self {
+            observer,
+            startup,
+            handover: Handover::default(),
+            count: 0,
+            elapsed: T::ZERO,
+            mode: SensorlessMode::OpenLoop,
+            offset: T::ZERO,
+        }
+    }
+
+    /// Change the handover conditions
+    pub fn with_handover(mut self, handover: Handover<T>) -> Self {
+        self.handover = handover;
+        self
+    }
+
+    /// Source of the angle
+    pub fn mode(&self) -> SensorlessMode {
+        self.mode
+    }
+
+    /// Go back to the start of the open-loop ramp
+    pub fn reset(&mut self) {
+        self.observer.reset();
+        self.startup.reset(T::ZERO);
+        self.count = 0;
+        self.elapsed = T::ZERO;
+        self.mode = SensorlessMode::OpenLoop;
+        self.offset = T::ZERO;
+    }
+
+    /// Feed new `alpha/beta` voltages and currents and return the angle to use
+    pub fn update(
+        &mut self,
+        voltage: Vector2<T>,
+        current: Vector2<T>,
+        delta_t: T,
+    ) -> SensorlessEstimate<T> {
+        let observed = self.observer.update(voltage, current, delta_t);
+
+        if self.mode == SensorlessMode::Failed {
+            return SensorlessEstimate {
+                angle: self.startup.angle(),
+                speed: self.startup.speed(),
+                mode: SensorlessMode::Failed,
+            };
+        }
+
+        if self.mode == SensorlessMode::OpenLoop {
+            let angle = self.startup.update(delta_t);
+            let speed = self.startup.speed();
+            let difference = wrap_angle(angle - observed.angle).map(|(difference, _)| difference);
+
+            let speed_ok =
+                (observed.speed - speed).abs() <= self.handover.speed_tolerance * speed.abs();
+            let angle_ok = difference
+                .is_some_and(|difference| difference.abs() <= self.handover.angle_tolerance);
+
+            if self.startup.finished() && speed_ok && angle_ok {
+                self.count += 1;
+            } else {
+                self.count = 0;
+            }
+
+            if self.count < self.handover.samples {
+                if self.startup.finished() {
+                    self.elapsed += delta_t;
+                    if self.elapsed > self.handover.timeout {
+                        self.mode = SensorlessMode::Failed;
+                    }
+                }
+
+                return SensorlessEstimate {
+                    angle,
+                    speed,
+                    mode: self.mode,
+                };
+            }
+
+            self.mode = SensorlessMode::ClosedLoop;
+            self.offset = difference.unwrap_or(T::ZERO);
+        }
+
+        // remove the remaining angle difference at the transition rate
+        let step = self.handover.transition_rate * delta_t;
+        self.offset -= self.offset.clamp(-step, step);
+
+        SensorlessEstimate {
+            angle: wrap_angle(observed.angle + self.offset)
+                .map_or(observed.angle, |(angle, _)| angle),
+            speed: observed.speed,
+            mode: SensorlessMode::ClosedLoop,
+        }
+    }
+}
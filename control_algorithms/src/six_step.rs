@@ -0,0 +1,232 @@
+//! Six-step (trapezoidal) commutation with Hall sensors
+//!
+//! A fallback to FOC that only needs the three Hall sensors. They give the rotor angle to
+//! within a sector of 60 electrical degrees, and two phases are driven at a time with the
+//! voltage vector 90 degrees ahead of the middle of the sector, while the third floats.
+//! It is also a quick way to spin a new motor before the current loops are tuned.
+//!
+//! Sectors are numbered 1 to 6 and centred on the rotor angles 0, 60, ..., 300 electrical
+//! degrees, so sector 1 spans -30 to 30 degrees.
+
+use crate::numeric::Real;
+
+/// High and low phase (a, b, c as 0, 1, 2) of each sector, turning forward
+const COMMUTATION: [(usize, usize); 6] = [(1, 2), (1, 0), (2, 0), (2, 1), (0, 1), (0, 2)];
+
+/// Phases switched high to lock the rotor at the middle of each sector
+const ALIGNMENT: [[bool; 3]; 6] = [
+    [true, false, false],
+    [true, true, false],
+    [false, true, false],
+    [false, true, true],
+    [false, false, true],
+    [true, false, true],
+];
+
+/// Steps of the learning, the first revolution lets the rotor settle and is not recorded
+const LEARNING_STEPS: usize = 12;
+
+/// Errors that can occur with the Hall sensors
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum HallError {
+    /// All sensors read the same (e.g. a disconnected sensor) or the state has more than
+    /// three bits
+    InvalidState,
+    /// The same Hall state is given for two sectors
+    InvalidTable,
+}
+
+/// Combine the Hall sensor readings into a state, sensor a being the lowest bit
+pub fn hall_state(a: bool, b: bool, c: bool) -> u8 {
+    u8::from(a) | u8::from(b) << 1 | u8::from(c) << 2
+}
+
+/// Mapping from Hall state to sector
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub struct HallTable {
+    /// Sector (1 to 6) of each Hall state, zero for the invalid states
+    sectors: [u8; 8],
+}
+
+impl Default for HallTable {
+    /// Sensors aligned with the phase axes, each high while the rotor is within 90
+    /// degrees of its phase: states 1, 3, 2, 6, 4, 5 in sectors 1 to 6
+    fn default() -> Self {
+        Self {
+            sectors: [0, 1, 3, 2, 5, 6, 4, 0],
+        }
+    }
+}
+
+impl HallTable {
+    /// Constructor from the Hall state read in each sector, e.g. from [`HallTable::states`]
+    /// after learning
+    pub fn new(states: [u8; 6]) -> Result<Self, HallError> {
+        let mut sectors = [0; 8];
+        for (sector, state) in states.into_iter().enumerate() {
+            if !(1..=6).contains(&state) {
+                return Err(HallError::InvalidState);
+            }
+            if sectors[usize::from(state)] != 0 {
+                return Err(HallError::InvalidTable);
+            }
+            sectors[usize::from(state)] = sector as u8 + 1;
+        }
+
+        Ok(Self { sectors })
+    }
+
+    /// Hall state read in each sector, to be stored or printed
+    pub fn states(&self) -> [u8; 6] {
+        let mut states = [0; 6];
+        for (state, &sector) in self.sectors.iter().enumerate() {
+            if sector != 0 {
+                states[usize::from(sector - 1)] = state as u8;
+            }
+        }
+        states
+    }
+
+    /// Sector of the rotor for a Hall state
+    pub fn sector(&self, state: u8) -> Result<u8, HallError> {
+        match self.sectors.get(usize::from(state)) {
+            Some(&sector) if sector != 0 => Ok(sector),
+            _ => Err(HallError::InvalidState),
+        }
+    }
+}
+
+/// What a half bridge does during a commutation step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PhaseOutput<T = f32> {
+    /// Switch with this duty cycle, between 0 and 1, a duty cycle of 0 keeps the low side on
+    Pwm(T),
+    /// Both switches off
+    Float,
+}
+
+/// Block commutation outputs of phases a, b and c for a rotor sector
+///
+/// `duty` is between -1 and 1. It switches the high phase, while the low phase keeps its
+/// low side on. A negative duty drives the vector 90 degrees behind the rotor instead,
+/// for reverse torque.
+pub fn block_commutation<T: Real>(sector: u8, duty: T) -> [PhaseOutput<T>; 3] {
+    assert!(
+        (1..=6).contains(&sector),
+        "Sector has to be between 1 and 6!"
+    );
+
+    let (high, low) = COMMUTATION[usize::from(sector - 1)];
+    let (high, low) = if duty < T::ZERO {
+        (low, high)
+    } else {
+        (high, low)
+    };
+
+    let mut outputs = [PhaseOutput::Float; 3];
+    outputs[high] = PhaseOutput::Pwm(duty.abs().min(T::ONE));
+    outputs[low] = PhaseOutput::Pwm(T::ZERO);
+    outputs
+}
+
+/// Six-step commutation from the Hall sensors
+pub struct SixStep {
+    /// Mapping from Hall state to sector
+    table: HallTable,
+}
+
+impl SixStep {
+    /// Constructor with the mapping of the sensors
+    pub fn new(table: HallTable) -> Self {
+        Self { table }
+    }
+
+    /// Outputs of phases a, b and c for a Hall state and a duty between -1 and 1
+    pub fn update<T: Real>(
+        &self,
+        hall_state: u8,
+        duty: T,
+    ) -> Result<[PhaseOutput<T>; 3], HallError> {
+        Ok(block_commutation(self.table.sector(hall_state)?, duty))
+    }
+}
+
+/// Learns the Hall table by locking the rotor at the middle of each sector in turn
+///
+/// Two revolutions are made, stepping forward: the first lets the rotor settle from
+/// wherever it was, the state at the end of each step of the second is recorded. The
+/// motor has to be unloaded.
+///
+/// This fixes the order and wiring of the sensors, but not an offset of their mounting
+/// within a sector, which still shifts the commutation by that angle.
+pub struct HallLearner<T: Real = f32> {
+    /// Duty cycle of the phases switched high to lock the rotor
+    duty: T,
+    /// Time spent in each step
+    step_time: T,
+    /// Current step
+    step: usize,
+    /// Time spent in the current step
+    elapsed: T,
+    /// Hall state recorded in each sector
+    states: [u8; 6],
+}
+
+impl<T: Real> HallLearner<T> {
+    /// Constructor with the duty cycle used to lock the rotor and the time spent in each
+    /// step, long enough for the rotor to settle
+    pub fn new(duty: T, step_time: T) -> Self {
+        Self {
+            duty,
+            step_time,
+            step: 0,
+            elapsed: T::ZERO,
+            states: [0; 6],
+        }
+    }
+
+    /// Start learning again
+    pub fn reset(&mut self) {
+        self.step = 0;
+        self.elapsed = T::ZERO;
+        self.states = [0; 6];
+    }
+
+    /// Whether all the steps are done
+    pub fn finished(&self) -> bool {
+        self.step >= LEARNING_STEPS
+    }
+
+    /// Duty cycles of phases a, b and c for the current step, zero once finished
+    pub fn duties(&self) -> [T; 3] {
+        if self.finished() {
+            return [T::ZERO; 3];
+        }
+
+        ALIGNMENT[self.step % 6].map(|high| if high { self.duty } else { T::ZERO })
+    }
+
+    /// Advance the learning with the current Hall state, returns the table once finished
+    pub fn update(&mut self, hall_state: u8, delta_t: T) -> Option<Result<HallTable, HallError>> {
+        if self.finished() {
+            return Some(HallTable::new(self.states));
+        }
+
+        self.elapsed += delta_t;
+        if self.elapsed < self.step_time {
+            return None;
+        }
+
+        self.elapsed = T::ZERO;
+        if self.step >= 6 {
+            self.states[self.step - 6] = hall_state;
+        }
+        self.step += 1;
+
+        if self.finished() {
+            Some(HallTable::new(self.states))
+        } else {
+            None
+        }
+    }
+}
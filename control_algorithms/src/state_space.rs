@@ -0,0 +1,193 @@
+//! Discrete state-space control: state feedback, reference feedforward and observers
+//!
+//! Runs the designs from `simulations/controller.py` (`StateFeedbackRegulator`) on the
+//! firmware. Gains are designed offline, e.g. by LQR, for the discretized model.
+
+pub use nalgebra::{SMatrix, SVector};
+
+use crate::linalg::invert;
+use crate::numeric::Real;
+
+/// Errors that can occur while setting up a state-space controller
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum StateSpaceError {
+    /// The closed loop has no finite DC gain, so no feedforward gain exists
+    SingularDcGain,
+}
+
+/// Discrete linear model `x[k+1] = A x[k] + B u[k]`, `y[k] = C x[k]` with `N` states,
+/// `U` inputs and `Y` outputs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StateSpace<T: Real, const N: usize, const U: usize, const Y: usize> {
+    /// State transition matrix
+    pub a: SMatrix<T, N, N>,
+    /// Input matrix
+    pub b: SMatrix<T, N, U>,
+    /// Output matrix
+    pub c: SMatrix<T, Y, N>,
+}
+
+impl<T: Real, const N: usize, const U: usize, const Y: usize> StateSpace<T, N, U, Y> {
+    /// Constructor with field values
+    pub fn new(a: SMatrix<T, N, N>, b: SMatrix<T, N, U>, c: SMatrix<T, Y, N>) -> Self {
+        Self { a, b, c }
+    }
+
+    /// Next state for the given state and input
+    pub fn step(&self, state: &SVector<T, N>, input: &SVector<T, U>) -> SVector<T, N> {
+        self.a * state + self.b * input
+    }
+
+    /// Output for the given state
+    pub fn output(&self, state: &SVector<T, N>) -> SVector<T, Y> {
+        self.c * state
+    }
+}
+
+impl<T: Real, const N: usize, const U: usize> StateSpace<T, N, U, U> {
+    /// Feedforward gain that gives unity DC gain from reference to output under the
+    /// state feedback `u = k_f r - K x`
+    ///
+    /// Discrete version of `feedfoward_gain` in `simulations/controller.py`:
+    /// `k_f = (C (I - A + B K)^-1 B)^-1`.
+    pub fn feedforward_gain(
+        &self,
+        k: &SMatrix<T, U, N>,
+    ) -> Result<SMatrix<T, U, U>, StateSpaceError> {
+        let closed_loop = SMatrix::<T, N, N>::identity() - self.a + self.b * k;
+        let closed_loop = invert(closed_loop).ok_or(StateSpaceError::SingularDcGain)?;
+
+        invert(self.c * closed_loop * self.b).ok_or(StateSpaceError::SingularDcGain)
+    }
+}
+
+/// State-feedback controller `u = k_f r - K x`
+pub struct StateFeedback<T: Real, const N: usize, const U: usize, const Y: usize> {
+    /// Feedback gain
+    pub k: SMatrix<T, U, N>,
+    /// Reference feedforward gain
+    pub k_f: SMatrix<T, U, Y>,
+    /// Output limits (min, max), applied to every input
+    output_limits: Option<(T, T)>,
+}
+
+impl<T: Real, const N: usize, const U: usize, const Y: usize> StateFeedback<T, N, U, Y> {
+    /// Constructor with field values
+    pub fn new(k: SMatrix<T, U, N>, k_f: SMatrix<T, U, Y>) -> Self {
+        Self {
+            k,
+            k_f,
+            output_limits: None,
+        }
+    }
+
+    /// Clamp every input to [min, max]
+    pub fn with_output_limits(mut self, min: T, max: T) -> Self {
+        assert!(
+            min <= max,
+            "Minimum output cannot be greater than maximum output!"
+        );
+        self.output_limits = Some((min, max));
+        self
+    }
+
+    /// Calculate the input for the given reference and state
+    pub fn output(&self, reference: &SVector<T, Y>, state: &SVector<T, N>) -> SVector<T, U> {
+        let output = self.k_f * reference - self.k * state;
+
+        match self.output_limits {
+            Some((min, max)) => output.map(|value| value.clamp(min, max)),
+            None => output,
+        }
+    }
+}
+
+impl<T: Real, const N: usize, const U: usize> StateFeedback<T, N, U, U> {
+    /// Controller with the feedforward gain that tracks the reference without error
+    pub fn from_model(
+        model: &StateSpace<T, N, U, U>,
+        k: SMatrix<T, U, N>,
+    ) -> Result<Self, StateSpaceError> {
+        Ok(Self::new(k, model.feedforward_gain(&k)?))
+    }
+}
+
+/// Luenberger observer `x[k+1] = A x[k] + B u[k] + L (y[k] - C x[k])`
+pub struct LuenbergerObserver<T: Real, const N: usize, const U: usize, const Y: usize> {
+    /// Model of the plant
+    pub model: StateSpace<T, N, U, Y>,
+    /// Observer gain
+    pub l: SMatrix<T, N, Y>,
+    /// State estimate
+    x: SVector<T, N>,
+}
+
+impl<T: Real, const N: usize, const U: usize, const Y: usize> LuenbergerObserver<T, N, U, Y> {
+    /// Constructor with field values, starting from a zero state
+    pub fn new(model: StateSpace<T, N, U, Y>, l: SMatrix<T, N, Y>) -> Self {
+        Self {
+            model,
+            l,
+            x: SVector::zeros(),
+        }
+    }
+
+    /// Start from the given state estimate
+    pub fn with_initial_state(mut self, x: SVector<T, N>) -> Self {
+        self.x = x;
+        self
+    }
+
+    /// State estimate
+    pub fn state(&self) -> &SVector<T, N> {
+        &self.x
+    }
+
+    /// Overwrite the state estimate
+    pub fn set_state(&mut self, x: SVector<T, N>) {
+        self.x = x;
+    }
+
+    /// Feed the input applied and the output measured in this sample, and return the
+    /// estimate for the next sample
+    pub fn update(&mut self, input: &SVector<T, U>, output: &SVector<T, Y>) -> &SVector<T, N> {
+        let error = output - self.model.output(&self.x);
+        self.x = self.model.step(&self.x, input) + self.l * error;
+        &self.x
+    }
+}
+
+/// Observer-based state-feedback regulator
+///
+/// Counterpart of `StateFeedbackRegulator` in `simulations/controller.py`, with the
+/// state estimated from the measured outputs.
+pub struct StateFeedbackRegulator<T: Real, const N: usize, const U: usize, const Y: usize> {
+    /// State-feedback controller
+    pub controller: StateFeedback<T, N, U, Y>,
+    /// Observer supplying the state
+    pub observer: LuenbergerObserver<T, N, U, Y>,
+}
+
+impl<T: Real, const N: usize, const U: usize, const Y: usize> StateFeedbackRegulator<T, N, U, Y> {
+    /// Constructor with field values
+    pub fn new(
+        controller: StateFeedback<T, N, U, Y>,
+        observer: LuenbergerObserver<T, N, U, Y>,
+    ) -> Self {
+        Self {
+            controller,
+            observer,
+        }
+    }
+
+    /// Calculate the input from the reference and the measured outputs
+    pub fn output(
+        &mut self,
+        reference: &SVector<T, Y>,
+        measurement: &SVector<T, Y>,
+    ) -> SVector<T, U> {
+        let output = self.controller.output(reference, self.observer.state());
+        self.observer.update(&output, measurement);
+        output
+    }
+}
@@ -1,80 +1,323 @@
 //! SVPWM implementation
-use core::f32::consts::PI;
-use defmt;
-use micromath::F32Ext;
-
-/// todo: documentation
-pub fn svpwm(v_alpha: f32, v_beta: f32, angle: f32, v_dc: f32, ts: f32) -> (f32, f32, f32) {
-    let v_reference: f32 = (v_alpha * v_alpha + v_beta * v_beta).sqrt();
-    if v_reference > v_dc {
-        panic!("Error: Reference Voltage cannot be greater than DC Link Voltage!")
-    }
-    let modulation_index: f32 = v_reference / v_dc;
-
-    let sector = match angle {
-        angle if 0. < angle && angle <= PI / 3. => 1,
-        angle if PI / 3. < angle && angle <= 2. * PI / 3. => 2,
-        angle if 2. * PI / 3. < angle && angle <= PI => 3,
-        angle if PI < angle && angle <= 4. * PI / 3. => 4,
-        angle if 4. * PI / 3. < angle && angle <= 5. * PI / 3. => 5,
-        angle if 5. * PI / 3. < angle && angle <= 2. * PI => 6,
-        _ => {
-            panic!("Invalid sector!");
+//!
+//! The reference voltage is given in the stationary frame, the sector is found from
+//! the signs of `alpha/beta` and the 60 and 120 degree lines, so no angle is needed.
+//! Duties are normalized to the switching period and can be given to the PWM directly.
+
+use core::f32::consts::FRAC_2_PI;
+
+use crate::numeric::Real;
+
+/// sqrt(3) / 2
+const SQRT_3_2: f32 = 0.866_025_4;
+
+/// 1 / sqrt(3)
+const FRAC_1_SQRT_3: f32 = 0.577_350_26;
+
+/// Cosine and sine of the start angle of each sector
+const SECTOR_ROTATION: [(f32, f32); 6] = [
+    (1.0, 0.0),
+    (0.5, SQRT_3_2),
+    (-0.5, SQRT_3_2),
+    (-1.0, 0.0),
+    (-0.5, -SQRT_3_2),
+    (0.5, -SQRT_3_2),
+];
+
+/// Errors that can occur when calculating the duty cycles
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum SvpwmError {
+    /// The bus voltage is not positive or not finite
+    InvalidBusVoltage,
+    /// The reference voltage is infinite or not a number
+    InvalidReference,
+}
+
+/// Output of [`svpwm`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SvpwmOutput<T = f32> {
+    /// Duty cycles of phases a, b and c, between 0 and 1
+    pub duties: [T; 3],
+    /// Sector of the reference voltage, 1 to 6, starting at the alpha axis
+    pub sector: u8,
+    /// Whether the reference was outside the hexagon and had to be scaled down
+    pub saturated: bool,
+}
+
+/// Find the sector (0 to 5) of a stationary frame vector
+fn sector<T: Real>(v_alpha: T, v_beta: T) -> usize {
+    // beta / sqrt(3) is where the 60 (and 240) degree line is
+    let b = v_beta * T::from_f32(FRAC_1_SQRT_3);
+
+    if v_beta >= T::ZERO {
+        if v_alpha > b {
+            0
+        } else if v_alpha > -b {
+            1
+        } else {
+            2
         }
+    } else if v_alpha < b {
+        3
+    } else if v_alpha < -b {
+        4
+    } else {
+        5
+    }
+}
+
+/// Check that the bus voltage is positive and all inputs are finite
+pub(crate) fn check_inputs<T: Real>(v_alpha: T, v_beta: T, v_dc: T) -> Result<(), SvpwmError> {
+    if !v_dc.is_finite() || v_dc <= T::ZERO {
+        return Err(SvpwmError::InvalidBusVoltage);
+    }
+
+    if !v_alpha.is_finite() || !v_beta.is_finite() {
+        return Err(SvpwmError::InvalidReference);
+    }
+
+    Ok(())
+}
+
+/// Calculate center-aligned space vector PWM duty cycles
+///
+/// `v_alpha` and `v_beta` are the reference voltage after the (amplitude invariant)
+/// inverse park transform, and `v_dc` is the bus voltage. The linear range goes up to
+/// `v_dc / sqrt(3)`. References outside the hexagon are scaled back onto its edge,
+/// keeping their angle, and flagged as saturated.
+pub fn svpwm<T: Real>(v_alpha: T, v_beta: T, v_dc: T) -> Result<SvpwmOutput<T>, SvpwmError> {
+    check_inputs(v_alpha, v_beta, v_dc)?;
+
+    let sector = sector(v_alpha, v_beta);
+
+    // rotate the reference into the first sector
+    let (cos, sin) = SECTOR_ROTATION[sector];
+    let (cos, sin) = (T::from_f32(cos), T::from_f32(sin));
+    let x = cos * v_alpha + sin * v_beta;
+    let y = cos * v_beta - sin * v_alpha;
+
+    // on times of the vectors at the start and end of the sector
+    let scale = v_dc * T::from_f32(FRAC_1_SQRT_3);
+    let mut t1 = (T::from_f32(SQRT_3_2) * x - T::from_f32(0.5) * y) / scale;
+    let mut t2 = y / scale;
+
+    let saturated = t1 + t2 > T::ONE;
+    if saturated {
+        let total = t1 + t2;
+        t1 /= total;
+        t2 /= total;
+    }
+
+    let t0 = (T::ONE - t1 - t2).max(T::ZERO);
+    let half = t0 * T::from_f32(0.5);
+
+    let duties = match sector {
+        0 => [half + t1 + t2, half + t2, half],
+        1 => [half + t1, half + t1 + t2, half],
+        2 => [half, half + t1 + t2, half + t2],
+        3 => [half, half + t1, half + t1 + t2],
+        4 => [half + t2, half, half + t1 + t2],
+        _ => [half + t1 + t2, half, half + t1],
     };
 
-    // defmt::info!("angle = {}", angle);
-    // defmt::info!("sector = {}", sector);
+    Ok(SvpwmOutput {
+        duties: duties.map(|duty| duty.clamp(T::ZERO, T::ONE)),
+        sector: sector as u8 + 1,
+        saturated,
+    })
+}
 
-    let t1: f32 = modulation_index * ts * ((sector as f32) * PI / 3. - angle).sin();
-    let t2: f32 = modulation_index * ts * (angle - ((sector - 1) as f32) * PI / 3.).sin();
-    let t0: f32 = ts - t1 - t2;
+/// Modulation index and radius (over the bus voltage) of the circle that is clipped to
+/// the hexagon in overmodulation region I
+///
+/// Generated from the fundamental of the clipped circle,
+/// `3 / pi (2 R (pi / 6 - b) + 2 / sqrt(3) ln(tan(pi / 4 + b / 2)))` with
+/// `b = acos(1 / (sqrt(3) R))`, in steps of equal radius.
+const REGION_1: [(f32, f32); 17] = [
+    (0.906_899_7, 0.577_350_3),
+    (0.914_119_5, 0.582_932_5),
+    (0.920_066_7, 0.588_514_8),
+    (0.925_196, 0.594_097_1),
+    (0.929_671_8, 0.599_679_4),
+    (0.933_590_1, 0.605_261_6),
+    (0.937_016_2, 0.610_843_9),
+    (0.939_998_5, 0.616_426_2),
+    (0.942_574_8, 0.622_008_5),
+    (0.944_775_8, 0.627_590_7),
+    (0.946_626_9, 0.633_173),
+    (0.948_149_9, 0.638_755_3),
+    (0.949_363_4, 0.644_337_6),
+    (0.950_284, 0.649_919_8),
+    (0.950_926_2, 0.655_502_1),
+    (0.951_303, 0.661_084_4),
+    (0.951_426_2, 0.666_666_7),
+];
 
-    let t_a: f32;
-    let t_b: f32;
-    let t_c: f32;
+/// Modulation index and tangent of the hold angle in overmodulation region II
+///
+/// Generated from the fundamental of the hexagon with the vertices held,
+/// `6 / pi (2 / 3 sin(a) + 1 / sqrt(3) ln(tan(pi / 4 + (pi / 6 - a) / 2)))`, in steps of
+/// equal hold angle.
+const REGION_2: [(f32, f32); 17] = [
+    (0.951_426_2, 0.0),
+    (0.952_013_7, 0.032_736_6),
+    (0.953_656, 0.065_543_5),
+    (0.956_178_8, 0.098_491_4),
+    (0.959_414_8, 0.131_652_5),
+    (0.963_203_3, 0.165_100_7),
+    (0.967_389_4, 0.198_912_4),
+    (0.971_822_9, 0.233_167_3),
+    (0.976_358_3, 0.267_949_2),
+    (0.980_854_3, 0.303_346_7),
+    (0.985_172_9, 0.339_454_3),
+    (0.989_179_4, 0.376_373_3),
+    (0.992_742_1, 0.414_213_6),
+    (0.995_731_6, 0.453_094_1),
+    (0.998_020_9, 0.493_145_4),
+    (0.999_484_8, 0.534_511_1),
+    (1.0, 0.577_350_3),
+];
 
-    match sector {
-        1 => {
-            t_a = t0 * 0.5 + t1 + t2;
-            t_b = t0 * 0.5 + t2;
-            t_c = t0 * 0.5;
-        }
+/// Operating region of the modulator
+#[derive(Clone, Copy, Debug, Default, PartialEq, defmt::Format)]
+pub enum ModulationRegion {
+    /// The reference is inside the inscribed circle and is produced as is
+    #[default]
+    Linear,
+    /// The reference is enlarged and clipped to the hexagon
+    Overmodulation1,
+    /// The output is held at the vertices of the hexagon for part of each sector
+    Overmodulation2,
+    /// The reference is beyond the six-step fundamental, only the vertices are used
+    SixStep,
+}
 
-        2 => {
-            t_a = t0 * 0.5 + t1;
-            t_b = t0 * 0.5 + t1 + t2;
-            t_c = t0 * 0.5;
-        }
+/// Output of [`svpwm_overmodulation`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OvermodulationOutput<T = f32> {
+    /// Duty cycles of the modified reference
+    pub pwm: SvpwmOutput<T>,
+    /// Region the reference fell into
+    pub region: ModulationRegion,
+    /// Reference magnitude over the six-step fundamental, `2 V_dc / pi`
+    pub modulation_index: T,
+    /// Fundamental of the output over the reference magnitude
+    ///
+    /// One up to six-step (within the accuracy of the tables), falling with the
+    /// reference beyond that.
+    pub gain: T,
+}
 
-        3 => {
-            t_a = t0 * 0.5;
-            t_b = t0 * 0.5 + t1 + t2;
-            t_c = t0 * 0.5 + t2;
-        }
+/// Largest fundamental voltage the inverter can produce, reached in six-step operation
+pub fn max_fundamental<T: Real>(v_dc: T) -> T {
+    v_dc * T::from_f32(FRAC_2_PI)
+}
 
-        4 => {
-            t_a = t0 * 0.5;
-            t_b = t0 * 0.5 + t1;
-            t_c = t0 * 0.5 + t1 + t2;
+/// Linearly interpolate the second column of a table over the first
+fn interpolate<T: Real>(table: &[(f32, f32)], x: T) -> T {
+    for pair in table.windows(2) {
+        let (x_1, y_1) = (T::from_f32(pair[1].0), T::from_f32(pair[1].1));
+        if x <= x_1 {
+            let (x_0, y_0) = (T::from_f32(pair[0].0), T::from_f32(pair[0].1));
+            return y_0 + (y_1 - y_0) * (x - x_0) / (x_1 - x_0);
         }
+    }
 
-        5 => {
-            t_a = t0 * 0.5 + t2;
-            t_b = t0 * 0.5;
-            t_c = t0 * 0.5 + t1 + t2;
-        }
+    T::from_f32(table[table.len() - 1].1)
+}
 
-        6 => {
-            t_a = t0 * 0.5 + t1 + t2;
-            t_b = t0 * 0.5;
-            t_c = t0 * 0.5 + t1;
-        }
+/// Calculate space vector PWM duty cycles with overmodulation up to six-step
+///
+/// Same as [`svpwm`] in the linear range. Beyond it, a two-region scheme is used so that
+/// the fundamental of the output follows the reference magnitude:
+///
+/// - Region I: the circular trajectory is enlarged and clipped to the hexagon, making up
+///   for what is lost on the edges with what is gained near the vertices.
+/// - Region II: the output is held at the nearest vertex while the reference is within
+///   the hold angle of it. Otherwise it is clipped radially onto the hexagon at the
+///   reference angle, so it jumps from the vertex onto the edge at the hold angle. The
+///   angle is not stretched as in the scheme of Holtz, which keeps the trajectory
+///   continuous, so the output has somewhat larger low-order harmonics.
+///
+/// References above [`max_fundamental`] give six-step operation, which is reported
+/// through [`OvermodulationOutput::gain`]. Expects a (roughly) circular reference, as the
+/// compensation is designed for a full revolution.
+pub fn svpwm_overmodulation<T: Real>(
+    v_alpha: T,
+    v_beta: T,
+    v_dc: T,
+) -> Result<OvermodulationOutput<T>, SvpwmError> {
+    let linear = svpwm(v_alpha, v_beta, v_dc)?;
 
-        _ => {
-            defmt::panic!("Invalid Sector!")
-        }
+    let magnitude = (v_alpha * v_alpha + v_beta * v_beta).sqrt();
+    let modulation_index = magnitude / max_fundamental(v_dc);
+
+    if modulation_index <= T::from_f32(REGION_1[0].0) {
+        return Ok(OvermodulationOutput {
+            pwm: linear,
+            region: ModulationRegion::Linear,
+            modulation_index,
+            gain: T::ONE,
+        });
+    }
+
+    if modulation_index <= T::from_f32(REGION_2[0].0) {
+        // scale onto the enlarged circle, svpwm clips it to the hexagon
+        let radius = interpolate(&REGION_1, modulation_index) * v_dc;
+        let scale = radius / magnitude;
+
+        return Ok(OvermodulationOutput {
+            pwm: svpwm(v_alpha * scale, v_beta * scale, v_dc)?,
+            region: ModulationRegion::Overmodulation1,
+            modulation_index,
+            gain: T::ONE,
+        });
     }
 
-    (t_a, t_b, t_c)
+    let (region, hold, gain) = if modulation_index < T::ONE {
+        (
+            ModulationRegion::Overmodulation2,
+            interpolate(&REGION_2, modulation_index),
+            T::ONE,
+        )
+    } else {
+        (
+            ModulationRegion::SixStep,
+            T::from_f32(FRAC_1_SQRT_3),
+            T::ONE / modulation_index,
+        )
+    };
+
+    // rotate the reference into the first sector
+    let sector = usize::from(linear.sector - 1);
+    let (cos, sin) = SECTOR_ROTATION[sector];
+    let (cos, sin) = (T::from_f32(cos), T::from_f32(sin));
+    let x = cos * v_alpha + sin * v_beta;
+    let y = cos * v_beta - sin * v_alpha;
+
+    // the same, seen from the vertex at the end of the sector
+    let x_end = T::from_f32(0.5) * x + T::from_f32(SQRT_3_2) * y;
+    let y_end = T::from_f32(SQRT_3_2) * x - T::from_f32(0.5) * y;
+
+    let vertex_radius = v_dc * T::from_f32(2.0 / 3.0);
+    let (v_alpha, v_beta) = if y <= x * hold {
+        (cos * vertex_radius, sin * vertex_radius)
+    } else if y_end <= x_end * hold {
+        let (cos, sin) = SECTOR_ROTATION[(sector + 1) % 6];
+        (
+            T::from_f32(cos) * vertex_radius,
+            T::from_f32(sin) * vertex_radius,
+        )
+    } else {
+        // anything at the vertex radius is on or outside the hexagon, svpwm clips it
+        let scale = vertex_radius / magnitude;
+        (v_alpha * scale, v_beta * scale)
+    };
+
+    Ok(OvermodulationOutput {
+        pwm: svpwm(v_alpha, v_beta, v_dc)?,
+        region,
+        modulation_index,
+        gain,
+    })
 }
@@ -0,0 +1,110 @@
+//! Controller tuning from motor parameters
+//!
+//! Gains are calculated in `f32`, use [`PIDGains::cast`] to convert them to other numeric types.
+
+use core::f32::consts::{FRAC_PI_2, FRAC_PI_6, PI};
+
+use crate::numeric::Float;
+use crate::pid::PIDGains;
+
+/// Smallest phase margin that the current loop is allowed to be tuned for
+pub const MIN_PHASE_MARGIN: f32 = FRAC_PI_6;
+
+/// Smallest ratio between the current and speed loop bandwidths
+pub const MIN_BANDWIDTH_SEPARATION: f32 = 2.0;
+
+/// Errors that can occur while calculating controller gains
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum TuningError {
+    /// A parameter was zero, negative or not a finite number
+    InvalidParameter,
+    /// The bandwidth is too high for the sample time and loop delay
+    BandwidthTooHigh,
+    /// The speed loop bandwidth is too close to the current loop bandwidth
+    InsufficientBandwidthSeparation,
+}
+
+/// Check that a parameter is finite and strictly positive
+fn positive(value: f32) -> Result<f32, TuningError> {
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(TuningError::InvalidParameter)
+    }
+}
+
+/// Calculate PI gains for a d/q current loop
+///
+/// The integral zero cancels the pole of the phase resistance and inductance, and
+/// the proportional gain sets the crossover frequency to `bandwidth` [rad/s]. The
+/// zero is placed on the zero-order-hold equivalent of the plant pole, which corrects
+/// for the sample time of the discrete controller (`k_i` tends to `R * bandwidth` as
+/// the sample time goes to zero).
+///
+/// The loop delay is modelled as half a sample plus `pwm_delay` [s], and the
+/// bandwidth is rejected if the remaining phase margin is below [`MIN_PHASE_MARGIN`].
+pub fn current_loop_gains(
+    resistance: f32,
+    inductance: f32,
+    bandwidth: f32,
+    sample_time: f32,
+    pwm_delay: f32,
+) -> Result<PIDGains, TuningError> {
+    let resistance = positive(resistance)?;
+    let inductance = positive(inductance)?;
+    let bandwidth = positive(bandwidth)?;
+    let sample_time = positive(sample_time)?;
+
+    if !pwm_delay.is_finite() || pwm_delay < 0.0 {
+        return Err(TuningError::InvalidParameter);
+    }
+
+    // crossover must be below the Nyquist frequency and leave enough phase margin
+    let delay = 0.5 * sample_time + pwm_delay;
+    if bandwidth * sample_time >= PI || FRAC_PI_2 - bandwidth * delay < MIN_PHASE_MARGIN {
+        return Err(TuningError::BandwidthTooHigh);
+    }
+
+    // discrete plant pole
+    let pole = Float::exp(-resistance * sample_time / inductance);
+
+    let k_p = inductance * bandwidth;
+    let k_i = k_p * (1.0 - pole) / (pole * sample_time);
+
+    Ok(PIDGains { k_p, k_i, k_d: 0.0 })
+}
+
+/// Calculate PI gains for a speed loop using the symmetric optimum
+///
+/// The plant is the rotor inertia [kg m^2] driven by the torque constant [Nm/A]
+/// through a current loop with a bandwidth of `current_bandwidth` [rad/s]. The
+/// crossover is placed at `bandwidth` [rad/s], geometrically centered between the
+/// integral zero and the current loop pole. The current loop must be at least
+/// [`MIN_BANDWIDTH_SEPARATION`] times faster than the speed loop.
+pub fn speed_loop_gains(
+    inertia: f32,
+    torque_constant: f32,
+    bandwidth: f32,
+    current_bandwidth: f32,
+    sample_time: f32,
+) -> Result<PIDGains, TuningError> {
+    let inertia = positive(inertia)?;
+    let torque_constant = positive(torque_constant)?;
+    let bandwidth = positive(bandwidth)?;
+    let current_bandwidth = positive(current_bandwidth)?;
+    let sample_time = positive(sample_time)?;
+
+    if bandwidth * sample_time >= PI {
+        return Err(TuningError::BandwidthTooHigh);
+    }
+
+    let separation = current_bandwidth / bandwidth;
+    if separation < MIN_BANDWIDTH_SEPARATION {
+        return Err(TuningError::InsufficientBandwidthSeparation);
+    }
+
+    let k_p = inertia * bandwidth / torque_constant;
+    let k_i = k_p * bandwidth / separation;
+
+    Ok(PIDGains { k_p, k_i, k_d: 0.0 })
+}
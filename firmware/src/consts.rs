@@ -10,14 +10,20 @@ pub const SPI_FREQUENCY: Hertz = mhz(5);
 /// PWM Frequency
 pub const PWM_FREQUENCY: Hertz = khz(45);
 
+/// Dead time set in the DRV (`DeadTime::_200` in `setup_driver`), in seconds
+pub const DEAD_TIME: f32 = 200e-9;
+
+/// Phase current above which the full dead-time correction is applied, in amps
+pub const DEAD_TIME_CURRENT: f32 = 0.5;
+
 /// Angle filter cutoff frequency in hz
 pub const F_C: i32 = 850;
 
 /// Angle filter sampling frequency in khz
 pub const F_S: i32 = 17;
 
-// /// Speed filter window size
-// pub const WINDOW_SIZE: usize = 31;
+/// Angle tracking PLL bandwidth in rad/s
+pub const PLL_BANDWIDTH: f32 = 2000.0;
 
 /// Motor Phase Resistance
 pub const RESISTANCE: f32 = 6.2832e-3 + 2.5e-3 + 0.85e-3;
@@ -27,3 +33,28 @@ pub const INDUCTANCE: f32 = 2.56e-6;
 
 /// PI Controller Bandwidth
 pub const BANDWIDTH: f32 = 10000.0;
+
+/// Control loop sample time in seconds
+pub const SAMPLE_TIME: f32 = 1.0 / (F_S as f32 * 1e3);
+
+/// Delay between sampling and the PWM update in seconds
+pub const PWM_DELAY: f32 = SAMPLE_TIME;
+
+/// Battery (DC bus) voltage
+pub const BUS_VOLTAGE: f32 = 12.6;
+
+/// Voltage command that spans the whole duty cycle range, duties are
+/// `(v / BUS_VOLTAGE + 1) / 2` as the current loop gains were tuned with on hardware
+pub const MODULATION_VOLTAGE: f32 = 2.0 * BUS_VOLTAGE;
+
+/// Largest current reference in amps, reached at full throttle
+pub const MAX_CURRENT: f32 = 16.95;
+
+/// Largest (magnitude of) negative d-axis current injected by field weakening, in amps
+///
+/// Well below `MAX_CURRENT`, so that most of the current limit is left for torque and the
+/// magnets are not driven towards demagnetization.
+pub const MAX_FIELD_WEAKENING_CURRENT: f32 = 6.0;
+
+/// Integral gain of the field-weakening loop in A/s per unit of voltage utilization
+pub const FIELD_WEAKENING_GAIN: f32 = 2000.0;
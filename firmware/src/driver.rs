@@ -91,7 +91,7 @@ pub async fn setup_driver(drv: &mut Drv<'_>) -> Result<(), Error> {
 
     // - using latched shutdown for safety while testing control algorithms
     // - vds level is based on datasheet, 0.06 V would be a more conservative value
-    // - dead time is the default value
+    // - dead time is 200 ns instead of the default 100 ns, must match `consts::DEAD_TIME`
 
     let ocp_control = OcpControl::new()
         .with_t_retry(TRetry::_4ms)
@@ -2,6 +2,7 @@
 
 use core::f32::consts::PI;
 
+use control_algorithms::six_step::PhaseOutput;
 use embassy_stm32::timer::{
     complementary_pwm::ComplementaryPwm, AdvancedInstance4Channel, Channel,
 };
@@ -34,3 +35,17 @@ where
     let duty = (max * frac) as u16;
     pwm.set_duty(channel, duty);
 }
+
+/// Apply a six-step commutation output, a floating phase has both switches turned off
+pub fn set_phase_output<T>(pwm: &mut ComplementaryPwm<T>, output: PhaseOutput, channel: Channel)
+where
+    T: AdvancedInstance4Channel,
+{
+    match output {
+        PhaseOutput::Pwm(frac) => {
+            set_pwm_duty(pwm, frac, channel);
+            pwm.enable(channel);
+        }
+        PhaseOutput::Float => pwm.disable(channel),
+    }
+}
@@ -7,13 +7,22 @@ mod helpers;
 
 use core::f32::consts::PI;
 
-use consts::{BANDWIDTH, F_C, F_S, INDUCTANCE, PWM_FREQUENCY, RESISTANCE, SPI_FREQUENCY};
+use consts::{
+    BANDWIDTH, BUS_VOLTAGE, DEAD_TIME, DEAD_TIME_CURRENT, FIELD_WEAKENING_GAIN, F_C, F_S,
+    INDUCTANCE, MAX_CURRENT, MAX_FIELD_WEAKENING_CURRENT, MODULATION_VOLTAGE, PLL_BANDWIDTH,
+    PWM_DELAY, PWM_FREQUENCY, RESISTANCE, SAMPLE_TIME, SPI_FREQUENCY,
+};
 use control_algorithms::{
+    dead_time::DeadTimeCompensation,
+    field_weakening::FieldWeakening,
     foc::{
-        clarke_transform, inverse_clarke_transform, inverse_park_transform, park_transform,
-        Vector2, Vector3,
+        clarke_transform, inverse_park_transform, park_transform, Vector2, Vector3, VoltageLimit,
+        VoltageLimiter, VoltagePriority,
     },
-    pid::PIDController,
+    pid::{AntiWindup, PIDController},
+    pll::PhaseLockedLoop,
+    svpwm::{svpwm_overmodulation, ModulationRegion},
+    tuning::current_loop_gains,
 };
 use driver::{check_driver, report_status, setup_driver};
 use drv8323rs::Drv8323rs;
@@ -204,18 +213,20 @@ async fn main(spawner: Spawner) {
         Coefficients::<f32>::from_params(Type::LowPass, F_S.khz(), F_C.hz(), Q_BUTTERWORTH_F32)
             .unwrap();
 
-    let mut alpha_filter = DirectForm2Transposed::<f32>::new(coefficients);
-    let mut beta_filter = DirectForm2Transposed::<f32>::new(coefficients);
-
-    // let mut speed_filter = MedianFilter::new([0.0; WINDOW_SIZE]);
+    // tracks the sensor angle and speed without the lag of low-pass filtering
+    let mut angle_pll = PhaseLockedLoop::<f32>::new(PLL_BANDWIDTH);
+    let mut pll_locked = false;
 
     let mut i_a_filter = DirectForm2Transposed::<f32>::new(coefficients);
 
     let mut last_time = Instant::now(); // dt
 
-    // let mut last_angle = 0.0; // differentiating
+    let current_gains =
+        current_loop_gains(RESISTANCE, INDUCTANCE, BANDWIDTH, SAMPLE_TIME, PWM_DELAY).unwrap();
 
-    let mut pid_cal = PIDController::new(INDUCTANCE * BANDWIDTH, RESISTANCE * BANDWIDTH, 0.0, None);
+    let mut pid_cal = PIDController::from_gains(current_gains, None)
+        .with_output_limits(0.0, BUS_VOLTAGE)
+        .with_anti_windup(AntiWindup::Clamping);
 
     info!("Calibrating Initial Angle...");
 
@@ -233,28 +244,32 @@ async fn main(spawner: Spawner) {
         let feedback_data = adc.read().await.unwrap();
         let new_time = Instant::now();
 
-        let mut alpha = (feedback_data[0] - 1.24) / 2.0;
-        let mut beta = (feedback_data[1] - 1.24) / 2.0;
+        let alpha = (feedback_data[0] - 1.24) / 2.0;
+        let beta = (feedback_data[1] - 1.24) / 2.0;
 
         let mut i_a = (feedback_data[2] - 1.6336) / (10.0 * 2.5e-3);
 
         // apply filtering
-        alpha = alpha_filter.run(alpha);
-        beta = beta_filter.run(beta);
-
         i_a = i_a_filter.run(i_a);
 
+        // calculate time delta
+        let dt = new_time.duration_since(last_time).as_micros() as f32 * 1e-6;
+
         // calculate angle
-        let angle = f32::atan2(alpha, beta) * (180.0 / PI);
+        let estimate = if pll_locked {
+            angle_pll.update(alpha, beta, dt)
+        } else {
+            angle_pll.initialize(alpha, beta);
+            pll_locked = true;
+            angle_pll.estimate()
+        };
+        let angle = estimate.angle * (180.0 / PI);
 
         if i > 3000 {
             angle_cal += angle;
         }
 
-        // calculate time delta
-        let dt = new_time.duration_since(last_time).as_micros() as f32 * 1e-6;
-
-        let c_t = (pid_cal.output(3.0, i_a, dt) / 12.6).clamp(0.0, 1.0);
+        let c_t = pid_cal.output(3.0, i_a, dt).value / BUS_VOLTAGE;
 
         // calculate output
         helpers::set_pwm_duty(&mut pwm, c_t, Channel::Ch1);
@@ -287,28 +302,32 @@ async fn main(spawner: Spawner) {
         let feedback_data = adc.read().await.unwrap();
         let new_time = Instant::now();
 
-        let mut alpha = (feedback_data[0] - 1.24) / 2.0;
-        let mut beta = (feedback_data[1] - 1.24) / 2.0;
+        let alpha = (feedback_data[0] - 1.24) / 2.0;
+        let beta = (feedback_data[1] - 1.24) / 2.0;
 
         let mut i_a = (feedback_data[2] - 1.6336) / (10.0 * 2.5e-3);
 
         // apply filtering
-        alpha = alpha_filter.run(alpha);
-        beta = beta_filter.run(beta);
-
         i_a = i_a_filter.run(i_a);
 
+        // calculate time delta
+        let dt = new_time.duration_since(last_time).as_micros() as f32 * 1e-6;
+
         // calculate angle
-        let angle = f32::atan2(alpha, beta) * (180.0 / PI);
+        let estimate = if pll_locked {
+            angle_pll.update(alpha, beta, dt)
+        } else {
+            angle_pll.initialize(alpha, beta);
+            pll_locked = true;
+            angle_pll.estimate()
+        };
+        let angle = estimate.angle * (180.0 / PI);
 
         if i > 3000 {
             angle_cal += angle;
         }
 
-        // calculate time delta
-        let dt = new_time.duration_since(last_time).as_micros() as f32 * 1e-6;
-
-        let c_t = (pid_cal.output(3.0, i_a, dt) / 12.6).clamp(0.0, 1.0);
+        let c_t = pid_cal.output(3.0, i_a, dt).value / BUS_VOLTAGE;
 
         // calculate output
         helpers::set_pwm_duty(&mut pwm, c_t, Channel::Ch1);
@@ -335,12 +354,37 @@ async fn main(spawner: Spawner) {
     // let mut i_b_filter = DirectForm2Transposed::<f32>::new(coefficients);
     // let mut i_c_filter = DirectForm2Transposed::<f32>::new(coefficients);
 
-    let mut pid_d = PIDController::new(INDUCTANCE * BANDWIDTH, RESISTANCE * BANDWIDTH, 0.0, None);
-    let mut pid_q = PIDController::new(INDUCTANCE * BANDWIDTH, RESISTANCE * BANDWIDTH, 0.0, None);
+    let mut pid_d = PIDController::from_gains(current_gains, None)
+        .with_output_limits(-BUS_VOLTAGE, BUS_VOLTAGE)
+        .with_anti_windup(AntiWindup::Clamping);
+    let mut pid_q = PIDController::from_gains(current_gains, None)
+        .with_output_limits(-BUS_VOLTAGE, BUS_VOLTAGE)
+        .with_anti_windup(AntiWindup::Clamping);
+
+    // Allow up to six-step for transients, the modulator overmodulates to reach it, while
+    // field weakening keeps the steady state within the linear range
+    let voltage_limiter = VoltageLimiter::new(MODULATION_VOLTAGE)
+        .with_shape(VoltageLimit::SixStep)
+        .with_priority(VoltagePriority::DAxis);
+    let dead_time = DeadTimeCompensation::new(
+        DEAD_TIME,
+        PWM_FREQUENCY.0 as f32,
+        BUS_VOLTAGE,
+        DEAD_TIME_CURRENT,
+    );
+    let mut field_weakening = FieldWeakening::new(
+        0.0,
+        FIELD_WEAKENING_GAIN,
+        MAX_CURRENT,
+        MAX_FIELD_WEAKENING_CURRENT,
+    );
+    let mut v_dq_request = Vector2::<f32>::zeros();
 
     loop {
         let Some(throttle) = *THROTTLE.lock().await else {
             // info!("disabled...");
+            pll_locked = false;
+            field_weakening.reset();
             pwm.disable(Channel::Ch1);
             pwm.disable(Channel::Ch2);
             pwm.disable(Channel::Ch3);
@@ -360,23 +404,31 @@ async fn main(spawner: Spawner) {
         let feedback_data = adc.read().await.unwrap();
         let new_time = Instant::now();
 
-        let mut alpha = (feedback_data[0] - 1.24) / 2.0;
-        let mut beta = (feedback_data[1] - 1.24) / 2.0;
+        let alpha = (feedback_data[0] - 1.24) / 2.0;
+        let beta = (feedback_data[1] - 1.24) / 2.0;
 
         let i_a = (feedback_data[2] - 1.6336) / (10.0 * 2.5e-3);
         let i_b = (feedback_data[3] - 1.6372) / (10.0 * 2.5e-3);
         let i_c = (feedback_data[4] - 1.6395) / (10.0 * 2.5e-3);
 
         // apply filtering
-        alpha = alpha_filter.run(alpha);
-        beta = beta_filter.run(beta);
-
         // i_a = i_a_filter.run(i_a);
         // i_b = i_b_filter.run(i_b);
         // i_c = i_c_filter.run(i_c);
 
-        // calculate angle
-        let physical_angle = f32::atan2(alpha, beta) * (180.0 / PI);
+        // calculate time delta
+        let dt = new_time.duration_since(last_time).as_micros() as f32 * 1e-6;
+
+        // calculate angle and speed, re-locking after the controller was disabled
+        let estimate = if pll_locked {
+            angle_pll.update(alpha, beta, dt)
+        } else {
+            angle_pll.initialize(alpha, beta);
+            pll_locked = true;
+            angle_pll.estimate()
+        };
+
+        let physical_angle = estimate.angle * (180.0 / PI);
         let electrical_angle = (angle_offset - physical_angle) * 2.0;
 
         // back to radians :(
@@ -384,15 +436,11 @@ async fn main(spawner: Spawner) {
         let sin = angle_rads.sin();
         let cos = angle_rads.cos();
 
-        // calculate time delta
-        let dt = new_time.duration_since(last_time).as_micros() as f32 * 1e-6;
-
-        // calculate speed
-        // let mut _speed = (angle - last_angle) / (dt * 360.0);
-        // _speed = speed_filter.run(_speed);
+        // mechanical speed in revolutions per second
+        let _speed = estimate.speed / (2.0 * PI);
 
-        // info!("angle: {}", angle);
-        // info!("speed: {}", speed);
+        // info!("angle: {}", physical_angle);
+        // info!("speed: {}", _speed);
         // info!("sample_rate: {} kHz", 1e-3 / dt);
         // info!("i_a: {}", -i_a * 1e3);
         // info!("i_b: {} mA", i_b * 1e3);
@@ -406,33 +454,55 @@ async fn main(spawner: Spawner) {
         let i_d = i_dq[0];
         let i_q = i_dq[1];
 
+        // Weaken the field when the last requested voltage used up the linear range
+        let currents = field_weakening.update(v_dq_request, voltage_limiter.linear_voltage(), dt);
+
         // Torque control
-        let v_d = pid_d.output(0.0, i_d, dt);
-        let v_q = pid_q.output(throttle * 16.95, i_q, dt); // 3A as 100 %
+        let v_d = pid_d.output(currents.d_current, i_d, dt).value;
+        let v_q = pid_q
+            .output(currents.limit_q(throttle * MAX_CURRENT), i_q, dt)
+            .value;
+        v_dq_request = Vector2::<f32>::new(v_d, v_q);
+
+        // Keep the voltage vector within what the bus can supply
+        let v_dq = voltage_limiter.limit(v_dq_request, sin, cos);
 
         // Transform back to rotating frame
-        let v_dq = Vector2::<f32>::new(v_d, v_q);
-        let v_xyz = inverse_park_transform(v_dq, sin, cos);
-        let v_abc = inverse_clarke_transform(v_xyz);
+        let v_xyz = inverse_park_transform(v_dq.voltage, sin, cos);
+
+        // Modulate, zero voltage if the reference is invalid
+        let (duties, overmodulated) =
+            match svpwm_overmodulation(v_xyz[0], v_xyz[1], MODULATION_VOLTAGE) {
+                Ok(output) => (
+                    output.pwm.duties,
+                    output.region != ModulationRegion::Linear || output.gain < 1.0,
+                ),
+                Err(e) => {
+                    error!("Modulation error: {}", e);
+                    ([0.5; 3], true)
+                }
+            };
+
+        // Stop integrating while the voltage is limited or not produced linearly
+        pid_d.freeze_integrator(v_dq.saturation[0] != 0.0 || overmodulated);
+        pid_q.freeze_integrator(v_dq.saturation[1] != 0.0 || overmodulated);
 
-        let v_a = (v_abc[0] / 12.6).clamp(-1.0, 1.0);
-        let v_b = (v_abc[1] / 12.6).clamp(-1.0, 1.0);
-        let v_c = (v_abc[2] / 12.6).clamp(-1.0, 1.0);
+        // Make up for the voltage lost to the dead time
+        let duties = dead_time.compensate(duties, i_abc);
 
         // info!("throttle: {} angle: {}", throttle, electrical_angle);
         // info!("i_a: {} i_b: {} i_c: {}", i_a, i_b, i_c);
         // info!("i_d: {} i_q: {}", i_d, i_q);
-        // info!("v_a: {} v_b: {} v_c: {}", v_a, v_b, v_c);
+        // info!("duties: {}", duties);
         // info!("v_d: {} v_q: {}", v_d, v_q);
 
         // calculate output
-        helpers::set_pwm_duty(&mut pwm, (v_a + 1.0) / 2.0, Channel::Ch1);
-        helpers::set_pwm_duty(&mut pwm, (v_b + 1.0) / 2.0, Channel::Ch2);
-        helpers::set_pwm_duty(&mut pwm, (v_c + 1.0) / 2.0, Channel::Ch3);
+        helpers::set_pwm_duty(&mut pwm, duties[0], Channel::Ch1);
+        helpers::set_pwm_duty(&mut pwm, duties[1], Channel::Ch2);
+        helpers::set_pwm_duty(&mut pwm, duties[2], Channel::Ch3);
 
         // update last values
         last_time = new_time;
-        // last_angle = angle;
     }
 }
 